@@ -0,0 +1,451 @@
+//! Reading a single member out of a local tar or zip archive, using paths of
+//! the form `bundle.tar.gz!/config/app.toml`
+//!
+//! Each format is only compiled in if its `archive-*` feature is enabled.
+
+#[cfg(all(feature = "archive-tar", feature = "compression"))]
+use crate::compression::{Codec, Decoder};
+use crate::{Error, Result};
+use std::ffi::{OsStr, OsString};
+use std::fmt::{self, Debug};
+use std::fs::File;
+#[cfg(any(feature = "archive-zip", feature = "compression"))]
+use std::io;
+#[cfg(all(feature = "archive-tar", feature = "compression"))]
+use std::io::BufReader;
+use std::io::{Read, Result as IoResult, Seek, SeekFrom, Take};
+use std::path::{Path, PathBuf};
+
+/// Separates the path of the archive from the path of the member inside it
+pub(crate) const SEPARATOR: &str = "!/";
+
+/// The archive formats that clio knows how to read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ArchiveKind {
+    #[cfg(feature = "archive-tar")]
+    Tar,
+    #[cfg(all(feature = "archive-tar", feature = "compression"))]
+    CompressedTar(Codec),
+    #[cfg(feature = "archive-zip")]
+    Zip,
+}
+
+impl ArchiveKind {
+    /// Picks the format from the file name of the archive, e.g. `bundle.tar.gz`
+    fn from_path(path: &Path) -> Option<ArchiveKind> {
+        let name = path.file_name()?.to_str()?;
+        #[cfg(feature = "archive-zip")]
+        if name.ends_with(".zip") {
+            return Some(ArchiveKind::Zip);
+        }
+        #[cfg(feature = "archive-tar")]
+        if name.ends_with(".tar") {
+            return Some(ArchiveKind::Tar);
+        }
+        #[cfg(all(feature = "archive-tar", feature = "compression"))]
+        {
+            if name.ends_with(".tgz") {
+                return Codec::from_extension(Some(OsStr::new("gz")))
+                    .map(ArchiveKind::CompressedTar);
+            }
+            let stem = Path::new(path.file_stem()?);
+            if stem.extension() == Some(OsStr::new("tar")) {
+                return Codec::from_extension(path.extension()).map(ArchiveKind::CompressedTar);
+            }
+        }
+        None
+    }
+}
+
+/// A path to a member inside a local archive
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub(crate) struct ArchivePath {
+    pub(crate) kind: ArchiveKind,
+    pub(crate) archive: PathBuf,
+    pub(crate) member: PathBuf,
+    /// the original form i.e. `archive!/member`
+    joined: OsString,
+}
+
+impl ArchivePath {
+    /// Splits `archive!/member` returning `None` if there is no separator
+    /// or the part before it is not a known archive format
+    pub(crate) fn parse(path: &OsStr) -> Option<ArchivePath> {
+        let path = path.to_str()?;
+        let mut start = 0;
+        while let Some(found) = path[start..].find(SEPARATOR) {
+            let split = start + found;
+            let archive = Path::new(&path[..split]);
+            if let Some(kind) = ArchiveKind::from_path(archive) {
+                let member = &path[split + SEPARATOR.len()..];
+                return Some(ArchivePath {
+                    kind,
+                    archive: archive.to_owned(),
+                    member: member.into(),
+                    joined: path.into(),
+                });
+            }
+            start = split + SEPARATOR.len();
+        }
+        None
+    }
+
+    /// The original form of the path i.e. `archive!/member`
+    pub(crate) fn as_os_str(&self) -> &OsStr {
+        &self.joined
+    }
+
+    /// Rebuilds the joined form after the member path has been modified
+    pub(crate) fn update_joined(&mut self) {
+        let mut joined = self.archive.as_os_str().to_owned();
+        joined.push(SEPARATOR);
+        joined.push(&self.member);
+        self.joined = joined;
+    }
+
+    /// Checks that the archive contains the member, so that the error can be
+    /// reported when the path is validated rather than when it is opened
+    pub(crate) fn assert_member_exists(&self) -> Result<()> {
+        self.locate().map(|_| ())
+    }
+
+    /// Opens the member for reading
+    pub(crate) fn open(&self) -> Result<ArchiveReader> {
+        #[cfg(all(feature = "archive-tar", feature = "compression"))]
+        if let ArchiveKind::CompressedTar(codec) = self.kind {
+            // the stream can not seek, so the member is read straight on from where it was found
+            let file = BufReader::new(File::open(&self.archive)?);
+            let mut decoder = Counted::new(Decoder::new(Some(codec), file)?);
+            let member = locate_tar(&mut decoder, &self.member)?;
+            let skip = member.start.saturating_sub(decoder.count);
+            io::copy(&mut (&mut decoder).take(skip), &mut io::sink())?;
+            return Ok(ArchiveReader {
+                size: member.size,
+                #[cfg(feature = "archive-zip")]
+                crc32: None,
+                read: MemberRead::CompressedTar(Box::new(decoder.inner.take(member.stored_size))),
+            });
+        }
+        let member = self.locate()?;
+        let mut file = File::open(&self.archive)?;
+        file.seek(SeekFrom::Start(member.start))?;
+        let file = file.take(member.stored_size);
+        let read = match member.method {
+            Method::Stored => MemberRead::Stored(file),
+            #[cfg(feature = "archive-zip")]
+            Method::Deflated => MemberRead::Deflated(flate2::read::DeflateDecoder::new(file)),
+        };
+        Ok(ArchiveReader {
+            size: member.size,
+            #[cfg(feature = "archive-zip")]
+            crc32: member.crc32.map(|crc32| (crc32, flate2::Crc::new())),
+            read,
+        })
+    }
+
+    fn locate(&self) -> Result<Member> {
+        match self.kind {
+            #[cfg(feature = "archive-tar")]
+            ArchiveKind::Tar => locate_tar(File::open(&self.archive)?, &self.member),
+            #[cfg(all(feature = "archive-tar", feature = "compression"))]
+            ArchiveKind::CompressedTar(codec) => {
+                let file = BufReader::new(File::open(&self.archive)?);
+                locate_tar(Decoder::new(Some(codec), file)?, &self.member)
+            }
+            #[cfg(feature = "archive-zip")]
+            ArchiveKind::Zip => locate_zip(File::open(&self.archive)?, &self.member),
+        }
+    }
+}
+
+/// How the bytes of a member are stored in the archive
+#[derive(Debug, Clone, Copy)]
+enum Method {
+    /// stored as is at a fixed offset in the archive, or in the decompressed tar stream
+    Stored,
+    #[cfg(feature = "archive-zip")]
+    /// a raw deflate stream at a fixed offset in the archive file
+    Deflated,
+}
+
+/// Where to find a member inside an archive
+struct Member {
+    method: Method,
+    start: u64,
+    stored_size: u64,
+    size: u64,
+    /// the checksum of the uncompressed bytes, zip archives store one for each member
+    #[cfg(feature = "archive-zip")]
+    crc32: Option<u32>,
+}
+
+/// Counts the bytes read so that the position in a stream that can not seek is known
+#[cfg(all(feature = "archive-tar", feature = "compression"))]
+struct Counted<R> {
+    inner: R,
+    count: u64,
+}
+
+#[cfg(all(feature = "archive-tar", feature = "compression"))]
+impl<R> Counted<R> {
+    fn new(inner: R) -> Self {
+        Counted { inner, count: 0 }
+    }
+}
+
+#[cfg(all(feature = "archive-tar", feature = "compression"))]
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let read = self.inner.read(buf)?;
+        self.count += read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(feature = "archive-tar")]
+fn locate_tar<R: Read>(read: R, member: &Path) -> Result<Member> {
+    let mut archive = tar::Archive::new(read);
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let path = entry.path()?;
+        if path.strip_prefix("./").unwrap_or(&path) == member {
+            if entry.header().entry_type().is_dir() {
+                return Err(Error::dir_error());
+            }
+            return Ok(Member {
+                method: Method::Stored,
+                start: entry.raw_file_position(),
+                stored_size: entry.size(),
+                size: entry.size(),
+                #[cfg(feature = "archive-zip")]
+                crc32: None,
+            });
+        }
+    }
+    Err(Error::not_found_error())
+}
+
+#[cfg(feature = "archive-zip")]
+fn locate_zip(file: File, member: &Path) -> Result<Member> {
+    use zip::CompressionMethod;
+
+    let mut archive = zip::ZipArchive::new(file)?;
+    let member = member.to_str().ok_or_else(Error::not_found_error)?;
+    // the raw entry has the offset of the data so it can be read without borrowing the archive
+    for index in 0..archive.len() {
+        let entry = archive.by_index_raw(index)?;
+        if entry.name() != member {
+            continue;
+        }
+        if entry.is_dir() {
+            return Err(Error::dir_error());
+        }
+        let method = match entry.compression() {
+            CompressionMethod::Stored => Method::Stored,
+            CompressionMethod::Deflated => Method::Deflated,
+            _ => return Err(Error::other("unsupported zip compression method")),
+        };
+        return Ok(Member {
+            method,
+            start: entry.data_start(),
+            stored_size: entry.compressed_size(),
+            size: entry.size(),
+            crc32: Some(entry.crc32()),
+        });
+    }
+    Err(Error::not_found_error())
+}
+
+#[cfg(feature = "archive-zip")]
+impl From<zip::result::ZipError> for Error {
+    fn from(err: zip::result::ZipError) -> Self {
+        match err {
+            zip::result::ZipError::Io(err) => Error::Io(err),
+            zip::result::ZipError::FileNotFound => Error::not_found_error(),
+            err => Error::Io(io::Error::new(io::ErrorKind::InvalidData, err)),
+        }
+    }
+}
+
+enum MemberRead {
+    Stored(Take<File>),
+    #[cfg(all(feature = "archive-tar", feature = "compression"))]
+    CompressedTar(Box<Take<Decoder<BufReader<File>>>>),
+    #[cfg(feature = "archive-zip")]
+    Deflated(flate2::read::DeflateDecoder<Take<File>>),
+}
+
+/// A reader for the bytes of a single member of an archive
+pub(crate) struct ArchiveReader {
+    size: u64,
+    /// the expected checksum and the checksum of what has been read so far
+    #[cfg(feature = "archive-zip")]
+    crc32: Option<(u32, flate2::Crc)>,
+    read: MemberRead,
+}
+
+impl ArchiveReader {
+    /// The uncompressed size of the member
+    pub(crate) fn len(&self) -> u64 {
+        self.size
+    }
+}
+
+impl Read for ArchiveReader {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let read = match &mut self.read {
+            MemberRead::Stored(read) => read.read(buf)?,
+            #[cfg(all(feature = "archive-tar", feature = "compression"))]
+            MemberRead::CompressedTar(read) => read.read(buf)?,
+            #[cfg(feature = "archive-zip")]
+            MemberRead::Deflated(read) => read.read(buf)?,
+        };
+        #[cfg(feature = "archive-zip")]
+        if let Some((expected, crc)) = &mut self.crc32 {
+            crc.update(&buf[..read]);
+            if read == 0 && !buf.is_empty() && crc.sum() != *expected {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "archive member does not match its checksum",
+                ));
+            }
+        }
+        Ok(read)
+    }
+}
+
+impl Debug for ArchiveReader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ArchiveReader")
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Input, InputPath};
+
+    #[cfg(feature = "archive-tar")]
+    #[test]
+    fn test_tar_member() {
+        let tmp = tempfile::tempdir().unwrap();
+        let bundle = tmp.path().join("bundle.tar");
+        let mut builder = tar::Builder::new(File::create(&bundle).unwrap());
+        for (name, data) in [("config/app.toml", "a = 1"), ("other.txt", "other")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, data.as_bytes())
+                .unwrap();
+        }
+        builder.finish().unwrap();
+        drop(builder);
+
+        let mut arg = bundle.into_os_string();
+        arg.push("!/other.txt");
+        let mut input = Input::new(&arg).unwrap();
+        assert_eq!(input.len(), Some(5));
+        assert_eq!(input.path().file_name(), Some(OsStr::new("other.txt")));
+        assert_eq!(input.path().as_os_str(), arg);
+        let mut contents = String::new();
+        input.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "other");
+
+        let mut missing = arg.clone();
+        missing.push("-missing");
+        assert_eq!(
+            InputPath::new(&missing).unwrap_err().kind(),
+            std::io::ErrorKind::NotFound
+        );
+    }
+
+    #[cfg(feature = "archive-zip")]
+    #[test]
+    fn test_zip_member() {
+        use std::io::Write;
+        let tmp = tempfile::tempdir().unwrap();
+        let bundle = tmp.path().join("bundle.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&bundle).unwrap());
+        for method in [
+            zip::CompressionMethod::Stored,
+            zip::CompressionMethod::Deflated,
+        ] {
+            let options = zip::write::FileOptions::default().compression_method(method);
+            zip.start_file(format!("{method}.txt"), options).unwrap();
+            zip.write_all(format!("{method} data").as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+        drop(zip);
+
+        for method in ["Stored", "Deflated"] {
+            let mut arg = bundle.clone().into_os_string();
+            arg.push(format!("!/{method}.txt"));
+            InputPath::new(&arg).unwrap();
+            let mut input = Input::new(&arg).unwrap();
+            let mut contents = String::new();
+            input.read_to_string(&mut contents).unwrap();
+            assert_eq!(contents, format!("{method} data"));
+            assert_eq!(input.len(), Some(contents.len() as u64));
+        }
+
+        // corrupt the stored member so that it no longer matches its checksum
+        let mut bytes = std::fs::read(&bundle).unwrap();
+        let at = bytes.windows(11).position(|w| w == b"Stored data").unwrap();
+        bytes[at] = b's';
+        std::fs::write(&bundle, bytes).unwrap();
+        let mut arg = bundle.into_os_string();
+        arg.push("!/Stored.txt");
+        let mut contents = String::new();
+        let err = Input::new(&arg)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(all(feature = "archive-tar", feature = "compression-gzip"))]
+    #[test]
+    fn test_compressed_tar_member() {
+        let tmp = tempfile::tempdir().unwrap();
+        let bundle = tmp.path().join("bundle.tar.gz");
+        let gz = flate2::write::GzEncoder::new(
+            File::create(&bundle).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut builder = tar::Builder::new(gz);
+        for (name, data) in [("first.txt", "first"), ("./second.txt", "second")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, data.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        for (member, data) in [("first.txt", "first"), ("second.txt", "second")] {
+            let mut arg = bundle.clone().into_os_string();
+            arg.push(format!("!/{member}"));
+            InputPath::new(&arg).unwrap();
+            let mut input = Input::new(&arg).unwrap();
+            assert_eq!(input.len(), Some(data.len() as u64));
+            let mut contents = String::new();
+            input.read_to_string(&mut contents).unwrap();
+            assert_eq!(contents, data);
+        }
+    }
+
+    #[cfg(feature = "archive-tar")]
+    #[test]
+    fn test_set_extension_on_member() {
+        let mut path = crate::ClioPath::new("bundle.tar!/config/app.toml").unwrap();
+        path.set_extension("json");
+        assert_eq!(path.as_os_str(), "bundle.tar!/config/app.json");
+        assert_eq!(path.path(), Path::new("config/app.json"));
+    }
+}
@@ -3,21 +3,49 @@
 //!
 //! This module is only compiled if you enable the clap-parse feature
 
+#[cfg(feature = "http")]
+use crate::http::RequestTarget;
+#[cfg(any(feature = "http", unix))]
+use crate::path::ClioPathEnum;
 use crate::{assert_exists, assert_is_dir, assert_not_dir, ClioPath, Error, Result};
+#[cfg(feature = "http")]
+use crate::{HttpConfig, Method};
 use clap::builder::TypedValueParser;
 use clap::error::ErrorKind;
 use std::ffi::OsStr;
 use std::marker::PhantomData;
+#[cfg(feature = "http")]
+use std::path::Path;
+#[cfg(feature = "http")]
+use std::sync::Arc;
 
 /// A clap parser that converts [`&OsStr`](std::ffi::OsStr) to an [`Input`](crate::Input) or [`Output`](crate::Output)
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct OsStrParser<T> {
     exists: Option<bool>,
     is_dir: Option<bool>,
     is_file: Option<bool>,
     is_tty: Option<bool>,
     atomic: bool,
+    raw: bool,
+    keep_file_url: bool,
+    compression_level: Option<i32>,
     default_name: Option<&'static str>,
+    std_token: &'static str,
+    stderr_token: Option<&'static str>,
+    local_only: bool,
+    #[cfg(feature = "http")]
+    require_https: bool,
+    #[cfg(feature = "http")]
+    allowed_hosts: Option<&'static [&'static str]>,
+    #[cfg(feature = "http")]
+    http_config: Option<Arc<HttpConfig>>,
+    #[cfg(feature = "http")]
+    check_remote: bool,
+    #[cfg(feature = "http")]
+    http_method: Method,
+    #[cfg(feature = "http")]
+    multipart: Option<&'static str>,
     phantom: PhantomData<T>,
 }
 
@@ -29,7 +57,25 @@ impl<T> OsStrParser<T> {
             is_file: None,
             is_tty: None,
             default_name: None,
+            std_token: "-",
+            stderr_token: None,
             atomic: false,
+            raw: false,
+            keep_file_url: false,
+            compression_level: None,
+            local_only: false,
+            #[cfg(feature = "http")]
+            require_https: false,
+            #[cfg(feature = "http")]
+            allowed_hosts: None,
+            #[cfg(feature = "http")]
+            http_config: None,
+            #[cfg(feature = "http")]
+            check_remote: false,
+            #[cfg(feature = "http")]
+            http_method: Method::Put,
+            #[cfg(feature = "http")]
+            multipart: None,
             phantom: PhantomData,
         }
     }
@@ -54,7 +100,7 @@ impl<T> OsStrParser<T> {
         self
     }
 
-    /// If this path is for stdin/stdout they must be a pipe not a tty
+    /// If this path is for stdin/stdout or a file descriptor they must be a pipe not a tty
     pub fn not_tty(mut self) -> Self {
         self.is_tty = Some(false);
         self
@@ -67,15 +113,186 @@ impl<T> OsStrParser<T> {
         self
     }
 
+    /// Read and write the raw bytes of compressed files, rather than transparently
+    /// decompressing/compressing them based on the extension or magic bytes
+    pub fn raw(mut self) -> Self {
+        self.raw = true;
+        self
+    }
+
+    /// Show paths given as `file://` urls as the url rather than the local path,
+    /// see [`ClioPath::set_keep_file_url`]
+    pub fn keep_file_url(mut self) -> Self {
+        self.keep_file_url = true;
+        self
+    }
+
+    /// The level to use when compressing an output, e.g. `19` for a `.zst` file
+    ///
+    /// It is clamped to the range supported by the format.
+    pub fn compression_level(mut self, level: i32) -> Self {
+        self.compression_level = Some(level);
+        self
+    }
+
     /// The default name to use for the file if the path is a directory
     pub fn default_name(mut self, name: &'static str) -> Self {
         self.default_name = Some(name);
         self
     }
 
+    /// Use `token` rather than `'-'` for stdin/stdout, e.g. so that `-` can be a file name
+    ///
+    /// On Unix `/dev/stdin`, `/dev/stdout` and `/dev/stderr` still name the std streams.
+    pub fn std_token(mut self, token: &'static str) -> Self {
+        self.std_token = token;
+        self
+    }
+
+    /// Also accept `token` for stderr, e.g. `-2`, which is otherwise a file name
+    ///
+    /// Like `/dev/stderr` it can only be written to, so an input of `token` is an error.
+    pub fn stderr_token(mut self, token: &'static str) -> Self {
+        self.stderr_token = Some(token);
+        self
+    }
+
+    /// Reject urls, so the argument can only be a local file, `-` or a member of a local archive
+    ///
+    /// Use this for tools that run on untrusted input and should never contact the network.
+    pub fn local_only(mut self) -> Self {
+        self.local_only = true;
+        self
+    }
+
+    /// Reject urls that are not sent with https, so nothing is sent or received unencrypted
+    ///
+    /// An `s3://` url is checked against the endpoint it is sent to, and a `http+unix://` url is
+    /// allowed as its socket is on this machine. This only checks the url that was passed in,
+    /// a server can still redirect to a plain `http` url.
+    #[cfg(feature = "http")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+    pub fn require_https(mut self) -> Self {
+        self.require_https = true;
+        self
+    }
+
+    /// Reject urls unless their host is one of `hosts`, ignoring case
+    ///
+    /// Subdomains are not allowed unless they are listed too. An `s3://` url is checked against
+    /// the host of the endpoint it is sent to rather than its bucket, and a `http+unix://` url
+    /// against the path of its socket, which has to match exactly.
+    /// This only checks the url that was passed in, a server can still redirect to another host.
+    #[cfg(feature = "http")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+    pub fn allowed_hosts(mut self, hosts: &'static [&'static str]) -> Self {
+        self.allowed_hosts = Some(hosts);
+        self
+    }
+
+    /// Use this config for HTTP requests instead of the global one
+    ///
+    /// It is shared by every path the parser, and any clone of it, returns.
+    #[cfg(feature = "http")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+    pub fn http_config(mut self, config: HttpConfig) -> Self {
+        self.http_config = Some(Arc::new(config));
+        self
+    }
+
+    /// Check urls with a request to the server when the argument is parsed,
+    /// rather than waiting until it is opened
+    ///
+    /// An [`InputPath`](crate::InputPath) sends a `HEAD` request, so a missing or forbidden file
+    /// is a [`NotFound`](std::io::ErrorKind::NotFound) or [`PermissionDenied`](std::io::ErrorKind::PermissionDenied)
+    /// error just like a local file, and its size is kept for [`InputPath::len`](crate::InputPath::len).
+    ///
+    /// An [`OutputPath`](crate::OutputPath) sends an `OPTIONS` request and fails if the server refuses it
+    /// or says it does not allow the [`http_method`](Self::http_method). Many servers don't answer `OPTIONS` at all so anything else passes.
+    #[cfg(feature = "http")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+    pub fn check_remote(mut self) -> Self {
+        self.check_remote = true;
+        self
+    }
+
+    /// The method used to upload an output to a url, `PUT` by default
+    #[cfg(feature = "http")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+    pub fn http_method(mut self, method: Method) -> Self {
+        self.http_method = method;
+        self
+    }
+
+    /// Upload an output to a url as the file in a `multipart/form-data` form, in the field called `field`
+    ///
+    /// The file name sent is the [`file_name`](std::path::Path::file_name) of the url.
+    #[cfg(feature = "http")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+    pub fn multipart(mut self, field: &'static str) -> Self {
+        self.multipart = Some(field);
+        self
+    }
+
+    /// Checks the path is allowed by [`local_only`](Self::local_only), [`require_https`](Self::require_https)
+    /// and [`allowed_hosts`](Self::allowed_hosts) before anything else looks at it
+    fn check_policy(&self, path: &ClioPath) -> Result<()> {
+        if self.local_only && path.is_remote() {
+            return Err(Error::denied("Only local files are allowed, not urls"));
+        }
+        #[cfg(feature = "http")]
+        if let ClioPathEnum::Http(url) = &path.path {
+            if !self.require_https && self.allowed_hosts.is_none() {
+                return Ok(());
+            }
+            let config = self
+                .http_config
+                .clone()
+                .unwrap_or_else(HttpConfig::global_arc);
+            let (https, allowed) = match crate::http::request_target(url, &config)? {
+                RequestTarget::Host { https, host } => (
+                    https,
+                    self.allowed_hosts.map_or(true, |hosts| {
+                        hosts
+                            .iter()
+                            .any(|allowed| allowed.eq_ignore_ascii_case(&host))
+                    }),
+                ),
+                RequestTarget::Socket(socket) => (
+                    true,
+                    self.allowed_hosts.map_or(true, |hosts| {
+                        hosts.iter().any(|allowed| Path::new(allowed) == socket)
+                    }),
+                ),
+            };
+            if self.require_https && !https {
+                return Err(Error::denied("Only https urls are allowed"));
+            }
+            if !allowed {
+                return Err(Error::denied("The host of the url is not allowed"));
+            }
+        }
+        Ok(())
+    }
+
     fn validate(&self, value: &OsStr) -> Result<ClioPath> {
-        let mut path = ClioPath::new(value)?;
+        let mut path = ClioPath::with_std_token(value, self.std_token, self.stderr_token)?;
+        self.check_policy(&path)?;
+        #[cfg(unix)]
+        if let ClioPathEnum::Fd(fd) = &path.path {
+            fd.assert_open()?;
+        }
         path.atomic = self.atomic;
+        path.raw = self.raw;
+        path.keep_file_url = self.keep_file_url;
+        path.compression_level = self.compression_level;
+        #[cfg(feature = "http")]
+        {
+            path.http_config = self.http_config.clone();
+            path.check_remote = self.check_remote;
+            path.http_method = self.http_method;
+            path.multipart = self.multipart.map(str::to_owned);
+        }
         if path.is_local() {
             if let Some(name) = self.default_name {
                 if path.is_dir() || path.ends_with_slash() {
@@ -157,8 +374,8 @@ mod tests {
 
     fn temp() -> TempDir {
         let tmp = tempdir().expect("could not make tmp dir");
-        create_dir(&tmp.path().join("dir")).expect("could not create dir");
-        write(&tmp.path().join("file"), "contents").expect("could not create dir");
+        create_dir(tmp.path().join("dir")).expect("could not create dir");
+        write(tmp.path().join("file"), "contents").expect("could not create dir");
         tmp
     }
 
@@ -266,8 +483,241 @@ mod tests {
         use crate::Input;
         let stdin = Input::std();
         let arg = stdin.to_string();
-        println!("{:?}", arg);
         let parsed = Input::try_from(arg.as_str()).unwrap();
         assert!(parsed.is_std())
     }
+
+    #[cfg(any(feature = "http-ureq", feature = "http-curl", feature = "http-reqwest"))]
+    #[test]
+    fn test_check_remote() {
+        use crate::http::test_server;
+        use crate::InputPath;
+        let url = test_server::serve(|request| {
+            let _ = request.respond(tiny_http::Response::empty(404));
+        });
+        let cmd = clap::Command::new("test");
+        let missing = OsStr::new(&url);
+        let unchecked = OsStrParser::<InputPath>::new();
+        assert!(unchecked.parse_ref(&cmd, None, missing).is_ok());
+        let checked = OsStrParser::<InputPath>::new().check_remote();
+        assert!(checked.parse_ref(&cmd, None, missing).is_err());
+    }
+
+    #[test]
+    fn test_data_uri() {
+        use crate::InputPath;
+        let cmd = clap::Command::new("test");
+        let parser = OsStrParser::<InputPath>::new().exists().is_file();
+        let path = parser
+            .parse_ref(&cmd, None, OsStr::new("data:text/plain;base64,SGVsbG8="))
+            .unwrap();
+        assert_eq!(path.len(), Some(5));
+        let err = parser
+            .parse_ref(&cmd, None, OsStr::new("data:text/plain;base64,SGVsbG8=!"))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidValue);
+    }
+
+    #[test]
+    fn test_stderr_token() {
+        use crate::{InputPath, OutputPath};
+        use std::path::Path;
+        let validator = OsStrParser::<ClioPath>::new().stderr_token("-2");
+        let path = validator.validate(OsStr::new("-2")).unwrap();
+        assert!(path.is_std());
+        assert_eq!(path.to_string_lossy(), "-2");
+        assert_eq!(path.path(), Path::new("/dev/stderr"));
+        assert!(validator.validate(OsStr::new("-")).unwrap().is_std());
+        assert!(OutputPath::try_from(path.clone()).unwrap().is_std());
+        assert!(InputPath::try_from(path).is_err());
+        assert!(OsStrParser::<ClioPath>::new()
+            .validate(OsStr::new("-2"))
+            .unwrap()
+            .is_local());
+    }
+
+    #[test]
+    fn test_std_token() {
+        let validator = OsStrParser::<ClioPath>::new().std_token("STDIO");
+        let path = validator.validate(OsStr::new("STDIO")).unwrap();
+        assert!(path.is_std());
+        assert_eq!(path.to_string(), "STDIO");
+        assert_eq!(
+            validator.validate(OsStr::new(&path.to_string())).unwrap(),
+            path
+        );
+        assert!(validator.validate(OsStr::new("-")).unwrap().is_local());
+        #[cfg(unix)]
+        assert!(validator
+            .validate(OsStr::new("/dev/stderr"))
+            .unwrap()
+            .is_std());
+        assert!(OsStrParser::<ClioPath>::new()
+            .validate(OsStr::new("STDIO"))
+            .unwrap()
+            .is_local());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fd() {
+        let validator = OsStrParser::<ClioPath>::new().exists();
+        let path = validator.validate(OsStr::new("/dev/fd/0")).unwrap();
+        assert_eq!(path.as_os_str(), "/dev/fd/0");
+        assert!(validator.validate(OsStr::new("fd:987654")).is_err());
+        assert!(validator.validate(OsStr::new("fd:three")).is_err());
+    }
+
+    #[test]
+    fn test_local_only() {
+        let validator = OsStrParser::<ClioPath>::new().local_only();
+        validator.validate(OsStr::new("-")).unwrap();
+        validator.validate(OsStr::new("file.txt")).unwrap();
+        validator.validate(OsStr::new("data:,inline")).unwrap();
+        #[cfg(feature = "http")]
+        assert_eq!(
+            validator
+                .validate(OsStr::new("https://example.com/file.txt"))
+                .unwrap_err()
+                .kind(),
+            std::io::ErrorKind::PermissionDenied
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_url() {
+        let tmp = tempdir().expect("could not make tmp dir");
+        write(tmp.path().join("my file.txt"), "contents").expect("could not write file");
+        let url = format!("file://{}/my%20file.txt", tmp.path().display());
+
+        let validator = OsStrParser::<ClioPath>::new()
+            .exists()
+            .is_file()
+            .local_only();
+        let path = validator.validate(OsStr::new(&url)).unwrap();
+        assert_eq!(path.path(), tmp.path().join("my file.txt"));
+        assert_eq!(path.as_os_str(), tmp.path().join("my file.txt"));
+
+        let mut path = validator
+            .keep_file_url()
+            .validate(OsStr::new(&url))
+            .unwrap();
+        assert_eq!(path.as_os_str(), OsStr::new(&url));
+        // the url is only dropped once it no longer matches the path
+        path.set_extension("txt");
+        assert_eq!(path.as_os_str(), OsStr::new(&url));
+        path.set_file_name("other.txt");
+        assert_eq!(path.as_os_str(), tmp.path().join("other.txt"));
+        assert!(OsStrParser::<ClioPath>::new()
+            .exists()
+            .validate(OsStr::new(&format!("{url}.missing")))
+            .is_err());
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_url_policies() {
+        let https = OsStrParser::<ClioPath>::new().require_https();
+        https
+            .validate(OsStr::new("https://example.com/file"))
+            .unwrap();
+        assert!(https
+            .validate(OsStr::new("http://example.com/file"))
+            .is_err());
+        https.validate(OsStr::new("file")).unwrap();
+
+        let hosts =
+            OsStrParser::<ClioPath>::new().allowed_hosts(&["Example.com", "cdn.example.com"]);
+        hosts
+            .validate(OsStr::new("http://example.com/file"))
+            .unwrap();
+        hosts
+            .validate(OsStr::new("https://EXAMPLE.COM:8443/file"))
+            .unwrap();
+        hosts
+            .validate(OsStr::new("https://cdn.example.com/file"))
+            .unwrap();
+        assert!(hosts
+            .validate(OsStr::new("https://evil.example.com/file"))
+            .is_err());
+        assert!(hosts
+            .validate(OsStr::new("https://example.com.evil.net/file"))
+            .is_err());
+        hosts.validate(OsStr::new("-")).unwrap();
+    }
+
+    #[cfg(feature = "s3")]
+    #[test]
+    fn test_s3_url_policies() {
+        let url = OsStr::new("s3://bucket/key");
+        let local = HttpConfig::default().s3_endpoint("http://localhost:9000");
+        OsStrParser::<ClioPath>::new()
+            .require_https()
+            .validate(url)
+            .unwrap();
+        assert!(OsStrParser::<ClioPath>::new()
+            .require_https()
+            .http_config(local.clone())
+            .validate(url)
+            .is_err());
+
+        // the bucket is not the host the request goes to
+        assert!(OsStrParser::<ClioPath>::new()
+            .allowed_hosts(&["bucket"])
+            .validate(url)
+            .is_err());
+        OsStrParser::<ClioPath>::new()
+            .allowed_hosts(&["bucket.s3.us-east-1.amazonaws.com"])
+            .validate(url)
+            .unwrap();
+        OsStrParser::<ClioPath>::new()
+            .allowed_hosts(&["localhost"])
+            .http_config(local)
+            .validate(url)
+            .unwrap();
+    }
+
+    #[cfg(all(feature = "http", unix))]
+    #[test]
+    fn test_unix_url_policies() {
+        let url = OsStr::new("http+unix://%2Frun%2Fapp.sock/file");
+        OsStrParser::<ClioPath>::new()
+            .require_https()
+            .validate(url)
+            .unwrap();
+        OsStrParser::<ClioPath>::new()
+            .allowed_hosts(&["/run/app.sock"])
+            .validate(url)
+            .unwrap();
+        for hosts in [
+            &["/run/other.sock"],
+            &["%2Frun%2Fapp.sock"],
+            &["/run/App.sock"],
+        ] {
+            assert!(
+                OsStrParser::<ClioPath>::new()
+                    .allowed_hosts(hosts)
+                    .validate(url)
+                    .is_err(),
+                "{hosts:?}"
+            );
+        }
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_http_config_is_shared() {
+        let parser = OsStrParser::<ClioPath>::new()
+            .http_config(HttpConfig::default().user_agent("clio-test"))
+            .multipart("file");
+        let url = OsStr::new("https://example.com/file");
+        let first = parser.validate(url).unwrap();
+        let second = parser.clone().validate(url).unwrap();
+        assert!(Arc::ptr_eq(
+            first.http_config.as_ref().unwrap(),
+            second.http_config.as_ref().unwrap()
+        ));
+        assert_eq!(second.multipart.as_deref(), Some("file"));
+    }
 }
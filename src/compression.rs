@@ -0,0 +1,434 @@
+//! Transparent decompression of inputs and compression of outputs, picking the
+//! format from the file extension or, for input streams, from the magic bytes
+//! at the start of the data.
+//!
+//! Each format is only compiled in if its `compression-*` feature is enabled.
+
+use std::ffi::OsStr;
+use std::fmt::{self, Debug};
+use std::io::{
+    BufRead, Chain, Cursor, Error as IoError, ErrorKind, Read, Result as IoResult, Write,
+};
+#[cfg(feature = "compression-zstd")]
+use std::sync::Mutex;
+
+/// The compression formats that clio knows how to read and write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Codec {
+    #[cfg(feature = "compression-gzip")]
+    Gzip,
+    #[cfg(feature = "compression-zstd")]
+    Zstd,
+    #[cfg(feature = "compression-xz")]
+    Xz,
+    #[cfg(feature = "compression-bzip2")]
+    Bzip2,
+}
+
+impl Codec {
+    /// Picks the codec from a file extension e.g. the `gz` of `data.csv.gz`
+    pub(crate) fn from_extension(extension: Option<&OsStr>) -> Option<Codec> {
+        match extension?.to_str()? {
+            #[cfg(feature = "compression-gzip")]
+            "gz" => Some(Codec::Gzip),
+            #[cfg(feature = "compression-zstd")]
+            "zst" => Some(Codec::Zstd),
+            #[cfg(feature = "compression-xz")]
+            "xz" => Some(Codec::Xz),
+            #[cfg(feature = "compression-bzip2")]
+            "bz2" => Some(Codec::Bzip2),
+            _ => None,
+        }
+    }
+
+    /// The number of bytes needed to recognise every format
+    const MAGIC_LEN: usize = 10;
+
+    /// Picks the codec from the magic bytes at the start of a stream
+    #[allow(unused_variables)]
+    pub(crate) fn from_magic(bytes: &[u8]) -> Option<Codec> {
+        #[cfg(feature = "compression-gzip")]
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            return Some(Codec::Gzip);
+        }
+        #[cfg(feature = "compression-zstd")]
+        if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            return Some(Codec::Zstd);
+        }
+        #[cfg(feature = "compression-xz")]
+        if bytes.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            return Some(Codec::Xz);
+        }
+        #[cfg(feature = "compression-bzip2")]
+        if let [b'B', b'Z', b'h', b'1'..=b'9', block @ ..] = bytes {
+            // the magic of the first block, or of the end of the stream if it is empty
+            if block.starts_with(&[0x31, 0x41, 0x59, 0x26, 0x53, 0x59])
+                || block.starts_with(&[0x17, 0x72, 0x45, 0x38, 0x50, 0x90])
+            {
+                return Some(Codec::Bzip2);
+            }
+        }
+        None
+    }
+}
+
+/// The wrapped reader with the bytes read to check the magic put back in front of it
+type Prefixed<R> = Chain<Cursor<Vec<u8>>, R>;
+
+/// A reader that decompresses the data read from the wrapped [`BufRead`]
+///
+/// If the codec is not known up front then the magic bytes are checked on the
+/// first read, and if they do not match any known format the data is passed
+/// through untouched.
+pub(crate) enum Decoder<R: BufRead> {
+    /// waiting for the first read to check the magic bytes
+    Sniff(Option<R>),
+    /// data that is not compressed
+    Plain(Prefixed<R>),
+    #[cfg(feature = "compression-gzip")]
+    Gzip(flate2::bufread::MultiGzDecoder<Prefixed<R>>),
+    #[cfg(feature = "compression-zstd")]
+    /// the zstd context is not [`Sync`] so is wrapped in a [`Mutex`] to keep clap happy
+    Zstd(Mutex<zstd::stream::read::Decoder<'static, Prefixed<R>>>),
+    #[cfg(feature = "compression-xz")]
+    Xz(xz2::bufread::XzDecoder<Prefixed<R>>),
+    #[cfg(feature = "compression-bzip2")]
+    Bzip2(bzip2::bufread::MultiBzDecoder<Prefixed<R>>),
+}
+
+impl<R: BufRead> Decoder<R> {
+    /// Wraps `read` in a decoder for `codec`, or if it is `None` then checks the
+    /// magic bytes to pick one when the first read happens
+    pub(crate) fn new(codec: Option<Codec>, read: R) -> IoResult<Self> {
+        match codec {
+            Some(codec) => Self::with_codec(Some(codec), Cursor::new(vec![]).chain(read)),
+            None => Ok(Self::sniff(read)),
+        }
+    }
+
+    /// Wraps `read` in a decoder that checks the magic bytes to pick the codec
+    pub(crate) fn sniff(read: R) -> Self {
+        Decoder::Sniff(Some(read))
+    }
+
+    fn with_codec(codec: Option<Codec>, read: Prefixed<R>) -> IoResult<Self> {
+        Ok(match codec {
+            None => Decoder::Plain(read),
+            #[cfg(feature = "compression-gzip")]
+            Some(Codec::Gzip) => Decoder::Gzip(flate2::bufread::MultiGzDecoder::new(read)),
+            #[cfg(feature = "compression-zstd")]
+            Some(Codec::Zstd) => {
+                Decoder::Zstd(Mutex::new(zstd::stream::read::Decoder::with_buffer(read)?))
+            }
+            #[cfg(feature = "compression-xz")]
+            Some(Codec::Xz) => Decoder::Xz(xz2::bufread::XzDecoder::new_multi_decoder(read)),
+            #[cfg(feature = "compression-bzip2")]
+            Some(Codec::Bzip2) => Decoder::Bzip2(bzip2::bufread::MultiBzDecoder::new(read)),
+        })
+    }
+}
+
+impl<R: BufRead> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        if let Decoder::Sniff(slot) = self {
+            let read = slot
+                .as_mut()
+                .ok_or_else(|| IoError::new(ErrorKind::Other, "decompression failed to start"))?;
+            // a single fill_buf might return less than the whole magic, so keep reading until
+            // there is enough of it or the stream ends
+            let mut magic = Vec::with_capacity(Codec::MAGIC_LEN);
+            read.take(Codec::MAGIC_LEN as u64).read_to_end(&mut magic)?;
+            let codec = Codec::from_magic(&magic);
+            let read = slot
+                .take()
+                .ok_or_else(|| IoError::new(ErrorKind::Other, "decompression failed to start"))?;
+            *self = Self::with_codec(codec, Cursor::new(magic).chain(read))?;
+        }
+        match self {
+            Decoder::Sniff(_) => unreachable!("codec is picked before reading"),
+            Decoder::Plain(read) => read.read(buf),
+            #[cfg(feature = "compression-gzip")]
+            Decoder::Gzip(read) => read.read(buf),
+            #[cfg(feature = "compression-zstd")]
+            Decoder::Zstd(read) => read
+                .get_mut()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .read(buf),
+            #[cfg(feature = "compression-xz")]
+            Decoder::Xz(read) => read.read(buf),
+            #[cfg(feature = "compression-bzip2")]
+            Decoder::Bzip2(read) => read.read(buf),
+        }
+    }
+}
+
+impl<R: BufRead> Debug for Decoder<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let codec = match self {
+            Decoder::Sniff(_) => "unknown",
+            Decoder::Plain(_) => "none",
+            #[cfg(feature = "compression-gzip")]
+            Decoder::Gzip(_) => "gzip",
+            #[cfg(feature = "compression-zstd")]
+            Decoder::Zstd(_) => "zstd",
+            #[cfg(feature = "compression-xz")]
+            Decoder::Xz(_) => "xz",
+            #[cfg(feature = "compression-bzip2")]
+            Decoder::Bzip2(_) => "bzip2",
+        };
+        f.debug_struct("Decoder").field("codec", &codec).finish()
+    }
+}
+
+/// A writer that compresses the data before writing it to the wrapped [`Write`]
+///
+/// [`finish`](Encoder::finish) must be called to write the trailer, otherwise
+/// the data may be truncated.
+pub(crate) enum Encoder<W: Write> {
+    #[cfg(feature = "compression-gzip")]
+    Gzip(flate2::write::GzEncoder<W>),
+    #[cfg(feature = "compression-zstd")]
+    Zstd(Mutex<zstd::stream::write::Encoder<'static, W>>),
+    #[cfg(feature = "compression-xz")]
+    Xz(xz2::write::XzEncoder<W>),
+    #[cfg(feature = "compression-bzip2")]
+    Bzip2(bzip2::write::BzEncoder<W>),
+}
+
+impl<W: Write> Encoder<W> {
+    /// Wraps `write` in an encoder for `codec`
+    ///
+    /// The level is clamped to the range supported by the codec, if it is `None`
+    /// the codec's default level is used.
+    #[allow(unused_variables)]
+    pub(crate) fn new(codec: Codec, write: W, level: Option<i32>) -> IoResult<Self> {
+        let level = |min: i32, default: i32, max: i32| level.unwrap_or(default).clamp(min, max);
+        Ok(match codec {
+            #[cfg(feature = "compression-gzip")]
+            Codec::Gzip => Encoder::Gzip(flate2::write::GzEncoder::new(
+                write,
+                flate2::Compression::new(level(0, 6, 9) as u32),
+            )),
+            #[cfg(feature = "compression-zstd")]
+            Codec::Zstd => Encoder::Zstd(Mutex::new(zstd::stream::write::Encoder::new(
+                write,
+                level(1, zstd::DEFAULT_COMPRESSION_LEVEL, 22),
+            )?)),
+            #[cfg(feature = "compression-xz")]
+            Codec::Xz => Encoder::Xz(xz2::write::XzEncoder::new(write, level(0, 6, 9) as u32)),
+            #[cfg(feature = "compression-bzip2")]
+            Codec::Bzip2 => Encoder::Bzip2(bzip2::write::BzEncoder::new(
+                write,
+                bzip2::Compression::new(level(1, 6, 9) as u32),
+            )),
+        })
+    }
+
+    /// Writes the trailer and returns the wrapped writer so it can be finished too
+    pub(crate) fn finish(self) -> IoResult<W> {
+        match self {
+            #[cfg(feature = "compression-gzip")]
+            Encoder::Gzip(write) => write.finish(),
+            #[cfg(feature = "compression-zstd")]
+            Encoder::Zstd(write) => write
+                .into_inner()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .finish(),
+            #[cfg(feature = "compression-xz")]
+            Encoder::Xz(write) => write.finish(),
+            #[cfg(feature = "compression-bzip2")]
+            Encoder::Bzip2(write) => write.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        match self {
+            #[cfg(feature = "compression-gzip")]
+            Encoder::Gzip(write) => write.write(buf),
+            #[cfg(feature = "compression-zstd")]
+            Encoder::Zstd(write) => write
+                .get_mut()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .write(buf),
+            #[cfg(feature = "compression-xz")]
+            Encoder::Xz(write) => write.write(buf),
+            #[cfg(feature = "compression-bzip2")]
+            Encoder::Bzip2(write) => write.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        match self {
+            #[cfg(feature = "compression-gzip")]
+            Encoder::Gzip(write) => write.flush(),
+            #[cfg(feature = "compression-zstd")]
+            Encoder::Zstd(write) => write
+                .get_mut()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .flush(),
+            #[cfg(feature = "compression-xz")]
+            Encoder::Xz(write) => write.flush(),
+            #[cfg(feature = "compression-bzip2")]
+            Encoder::Bzip2(write) => write.flush(),
+        }
+    }
+}
+
+impl<W: Write> Debug for Encoder<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let codec = match self {
+            #[cfg(feature = "compression-gzip")]
+            Encoder::Gzip(_) => "gzip",
+            #[cfg(feature = "compression-zstd")]
+            Encoder::Zstd(_) => "zstd",
+            #[cfg(feature = "compression-xz")]
+            Encoder::Xz(_) => "xz",
+            #[cfg(feature = "compression-bzip2")]
+            Encoder::Bzip2(_) => "bzip2",
+        };
+        f.debug_struct("Encoder").field("codec", &codec).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn decode(codec: Option<Codec>, data: &[u8]) -> Vec<u8> {
+        let mut decoder = Decoder::new(codec, Cursor::new(data)).unwrap();
+        let mut out = vec![];
+        decoder.read_to_end(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_plain_passes_through() {
+        assert_eq!(decode(None, b"hello world"), b"hello world");
+        assert_eq!(decode(None, b""), b"");
+    }
+
+    #[cfg(feature = "compression-gzip")]
+    #[test]
+    fn test_gzip_sniffed() {
+        use std::io::Write;
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(b"hello world").unwrap();
+        let data = encoder.finish().unwrap();
+        assert_eq!(Codec::from_magic(&data), Some(Codec::Gzip));
+        assert_eq!(decode(None, &data), b"hello world");
+        assert_eq!(decode(Some(Codec::Gzip), &data), b"hello world");
+    }
+
+    #[cfg(feature = "compression-gzip")]
+    #[test]
+    fn test_input_from_extension() {
+        use crate::{ClioPath, Input};
+        use std::io::Write;
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join("data.csv.gz");
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(b"a,b,c").unwrap();
+        std::fs::write(&file, encoder.finish().unwrap()).unwrap();
+
+        let mut input = Input::new(&file).unwrap();
+        assert_eq!(input.len(), None);
+        let mut out = String::new();
+        input.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "a,b,c");
+
+        let mut path = ClioPath::new(&file).unwrap();
+        path.raw = true;
+        let input = Input::new(path).unwrap();
+        assert!(input.len().is_some());
+    }
+
+    #[test]
+    fn test_round_trip_all_codecs() {
+        #[allow(unused_mut)]
+        let mut codecs: Vec<Codec> = vec![];
+        #[cfg(feature = "compression-gzip")]
+        codecs.push(Codec::Gzip);
+        #[cfg(feature = "compression-zstd")]
+        codecs.push(Codec::Zstd);
+        #[cfg(feature = "compression-xz")]
+        codecs.push(Codec::Xz);
+        #[cfg(feature = "compression-bzip2")]
+        codecs.push(Codec::Bzip2);
+        for codec in codecs {
+            for level in [None, Some(0), Some(100)] {
+                let mut encoder = Encoder::new(codec, vec![], level).unwrap();
+                encoder.write_all(b"hello world").unwrap();
+                let data = encoder.finish().unwrap();
+                assert_eq!(Codec::from_magic(&data), Some(codec));
+                assert_eq!(decode(None, &data), b"hello world", "{codec:?}");
+            }
+        }
+    }
+
+    #[cfg(feature = "compression-gzip")]
+    #[test]
+    fn test_atomic_output_from_extension() {
+        use crate::{ClioPath, Output};
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join("report.json.gz");
+        let mut path = ClioPath::new(&file).unwrap();
+        path.atomic = true;
+        path.compression_level = Some(9);
+
+        let mut output = Output::new(path).unwrap();
+        output.write_all(b"{}").unwrap();
+        assert!(!file.exists());
+        output.finish().unwrap();
+
+        assert_eq!(decode(None, &std::fs::read(&file).unwrap()), b"{}");
+    }
+
+    #[test]
+    fn test_sniff_short_reads() {
+        use std::io::BufReader;
+        let codecs: Vec<Codec> = vec![
+            #[cfg(feature = "compression-gzip")]
+            Codec::Gzip,
+            #[cfg(feature = "compression-bzip2")]
+            Codec::Bzip2,
+        ];
+        for codec in codecs {
+            let mut encoder = Encoder::new(codec, vec![], None).unwrap();
+            encoder.write_all(b"hello world").unwrap();
+            let data = encoder.finish().unwrap();
+            // each fill_buf only returns a single byte
+            let read = BufReader::with_capacity(1, Cursor::new(data));
+            let mut decoder = Decoder::new(None, read).unwrap();
+            let mut out = vec![];
+            decoder.read_to_end(&mut out).unwrap();
+            assert_eq!(out, b"hello world", "{codec:?}");
+        }
+        assert_eq!(decode(None, b"BZh"), b"BZh");
+    }
+
+    #[cfg(feature = "compression-bzip2")]
+    #[test]
+    fn test_bzip2_magic() {
+        assert_eq!(Codec::from_magic(b"BZh, that was close"), None);
+        assert_eq!(Codec::from_magic(b"BZh9 not really"), None);
+        assert_eq!(decode(None, b"BZh9 not really"), b"BZh9 not really");
+
+        let empty = Encoder::new(Codec::Bzip2, vec![], None)
+            .unwrap()
+            .finish()
+            .unwrap();
+        assert_eq!(Codec::from_magic(&empty), Some(Codec::Bzip2));
+        assert_eq!(decode(None, &empty), b"");
+    }
+
+    #[cfg(feature = "compression-zstd")]
+    #[test]
+    fn test_zstd_sniffed() {
+        let data = zstd::encode_all(&b"hello world"[..], 3).unwrap();
+        assert_eq!(Codec::from_magic(&data), Some(Codec::Zstd));
+        assert_eq!(decode(None, &data), b"hello world");
+    }
+}
@@ -0,0 +1,236 @@
+//! Inline inputs written as `data:` urls, e.g. `data:text/plain;base64,SGVsbG8=`
+//!
+//! The format is described in [RFC 2397](https://www.rfc-editor.org/rfc/rfc2397).
+
+use crate::file_url::percent_decode;
+use crate::{Error, Result};
+use std::ffi::{OsStr, OsString};
+
+/// The media type used when a `data:` url does not give one
+const DEFAULT_MEDIA_TYPE: &str = "text/plain;charset=US-ASCII";
+
+/// A `data:` url and the bytes it contains
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub(crate) struct DataUri {
+    uri: OsString,
+    metadata: DataMetadata,
+    pub(crate) data: Vec<u8>,
+}
+
+impl DataUri {
+    /// Decodes `path` if it is a `data:` url, returning `None` if it is not one
+    pub(crate) fn parse(path: &OsStr) -> Option<Result<DataUri>> {
+        let uri = path.to_str()?;
+        let scheme = uri.get(..5)?;
+        if !scheme.eq_ignore_ascii_case("data:") {
+            return None;
+        }
+        Some(DataUri::decode(&uri[5..]).map(|(metadata, data)| DataUri {
+            uri: path.to_owned(),
+            metadata,
+            data,
+        }))
+    }
+
+    fn decode(uri: &str) -> Result<(DataMetadata, Vec<u8>)> {
+        let (header, data) = uri
+            .split_once(',')
+            .ok_or_else(|| Error::data_uri_error("A data url must have a ',' before the data"))?;
+        let (header, base64) = match header.rsplit_once(';') {
+            Some((header, encoding)) if encoding.eq_ignore_ascii_case("base64") => (header, true),
+            _ => (header, false),
+        };
+        let header = String::from_utf8(percent_decode(header))
+            .map_err(|_| Error::data_uri_error("The media type of a data url must be UTF-8"))?;
+        let media_type = match header.split(';').next().unwrap_or_default() {
+            "" if header.is_empty() => DEFAULT_MEDIA_TYPE.to_owned(),
+            // `data:;charset=utf-8,` keeps the default type but not its charset
+            "" => format!("text/plain{header}"),
+            essence if is_media_type(essence) => header.clone(),
+            _ => {
+                return Err(Error::data_uri_error(
+                    "The media type of a data url must be of the form type/subtype",
+                ))
+            }
+        };
+        let data = percent_decode(data);
+        let data = if base64 { base64_decode(&data)? } else { data };
+        Ok((DataMetadata { media_type }, data))
+    }
+
+    pub(crate) fn as_os_str(&self) -> &OsStr {
+        &self.uri
+    }
+
+    pub(crate) fn metadata(&self) -> &DataMetadata {
+        &self.metadata
+    }
+}
+
+fn is_media_type(essence: &str) -> bool {
+    let token = |part: &str| {
+        !part.is_empty()
+            && part
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"!#$&-^_.+".contains(&b))
+    };
+    matches!(essence.split_once('/'), Some((kind, subtype)) if token(kind) && token(subtype))
+}
+
+/// Decodes standard base64, ignoring whitespace and allowing the padding to be left out
+fn base64_decode(encoded: &[u8]) -> Result<Vec<u8>> {
+    let invalid = || Error::data_uri_error("The data of a data url is not valid base64");
+    let mut decoded = Vec::with_capacity(encoded.len() / 4 * 3);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    let mut padding = 0;
+    for &byte in encoded.iter().filter(|b| !b.is_ascii_whitespace()) {
+        let value = match byte {
+            b'=' => {
+                padding += 1;
+                continue;
+            }
+            // nothing but padding can follow padding
+            _ if padding > 0 => return Err(invalid()),
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return Err(invalid()),
+        };
+        buffer = buffer << 6 | u32::from(value);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+        }
+    }
+    // 6 bits left over means a single character in the last group, which can't be a whole byte
+    if bits == 6 || padding > 2 {
+        return Err(invalid());
+    }
+    Ok(decoded)
+}
+
+/// Details of an [`Input`](crate::Input) read from a `data:` url
+///
+/// Returned by [`Input::data_metadata`](crate::Input::data_metadata).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DataMetadata {
+    media_type: String,
+}
+
+impl DataMetadata {
+    /// The media type including any parameters, e.g. `text/plain;charset=utf-8`
+    ///
+    /// If the url did not give one this is the default of `text/plain;charset=US-ASCII`.
+    pub fn media_type(&self) -> &str {
+        &self.media_type
+    }
+
+    /// The media type without its parameters, e.g. `text/plain`
+    pub fn essence(&self) -> &str {
+        self.media_type.split(';').next().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CachedInput, ClioPath, Input, InputPath, OutputPath};
+    use std::io::Read;
+
+    fn decode(uri: &str) -> Result<(String, Vec<u8>)> {
+        let uri = DataUri::parse(OsStr::new(uri)).unwrap()?;
+        Ok((uri.metadata.media_type, uri.data))
+    }
+
+    #[test]
+    fn test_not_data_uris() {
+        for path in ["data", "data.txt", "-", "/tmp/data:x", "dat"] {
+            assert!(DataUri::parse(OsStr::new(path)).is_none(), "{path}");
+        }
+    }
+
+    #[test]
+    fn test_decode() -> Result<()> {
+        assert_eq!(
+            decode("data:text/plain;base64,SGVsbG8=")?,
+            ("text/plain".to_owned(), b"Hello".to_vec())
+        );
+        assert_eq!(
+            decode("data:,A%20brief%20note")?,
+            (DEFAULT_MEDIA_TYPE.to_owned(), b"A brief note".to_vec())
+        );
+        assert_eq!(
+            decode("DATA:;charset=utf-8,caf%C3%A9")?,
+            (
+                "text/plain;charset=utf-8".to_owned(),
+                "café".as_bytes().to_vec()
+            )
+        );
+        assert_eq!(
+            decode("data:application/octet-stream;BASE64,AP8")?.1,
+            vec![0, 255]
+        );
+        assert_eq!(decode("data:;base64,SGVs%0AbG8")?.1, b"Hello");
+        assert_eq!(decode("data:,")?.1, b"");
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed() {
+        for uri in [
+            "data:text/plain",
+            "data:text;base64,SGVsbG8=",
+            "data:;base64,SGVsbG8=!",
+            "data:;base64,S",
+            "data:;base64,SG=Vs",
+            "data:;base64,SGVsbG8===",
+        ] {
+            let err = decode(uri).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput, "{uri}");
+        }
+    }
+
+    #[test]
+    fn test_input() -> Result<()> {
+        let uri = "data:text/plain;base64,SGVsbG8=";
+        let mut input = Input::new(uri)?;
+        assert_eq!(input.len(), Some(5));
+        assert!(input.can_seek());
+        assert!(!input.is_local());
+        assert_eq!(input.data_metadata().unwrap().media_type(), "text/plain");
+        assert_eq!(input.to_string(), uri);
+        let mut contents = String::new();
+        input.read_to_string(&mut contents)?;
+        assert_eq!(contents, "Hello");
+
+        let cached = CachedInput::new(uri)?;
+        assert_eq!(cached.get_data(), b"Hello");
+        assert_eq!(cached.data_metadata().unwrap().essence(), "text/plain");
+
+        let path = InputPath::new(uri)?;
+        assert_eq!(path.len(), Some(5));
+        assert!(InputPath::new("data:text/plain;base64,SGVsbG8=!").is_err());
+
+        assert!(OutputPath::new(uri).is_err());
+        assert!(ClioPath::new(uri)?.create().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_local_file_named_like_a_data_uri() -> Result<()> {
+        // made in the working directory so that `./` is the only thing in front of `data:`
+        let file = tempfile::Builder::new().prefix("data:").tempfile_in(".")?;
+        std::fs::write(file.path(), "local")?;
+        let name = file.path().file_name().unwrap().to_str().unwrap();
+        let path = ClioPath::new(format!("./{name}"))?;
+        assert!(path.is_local());
+        let mut contents = String::new();
+        Input::new(path)?.read_to_string(&mut contents)?;
+        assert_eq!(contents, "local");
+        Ok(())
+    }
+}
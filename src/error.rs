@@ -64,6 +64,8 @@ impl Error {
             Error::Http { code, message: _ } => match code {
                 404 | 410 => ErrorKind::NotFound,
                 401 | 403 => ErrorKind::PermissionDenied,
+                // a conditional write found the file already exists or was changed
+                412 => ErrorKind::AlreadyExists,
                 _ => ErrorKind::Other,
             },
         }
@@ -73,6 +75,86 @@ impl Error {
         Error::Io(IoError::new(ErrorKind::Other, message))
     }
 
+    #[cfg(any(feature = "http", feature = "clap-parse"))]
+    pub(crate) fn denied(message: &'static str) -> Self {
+        Error::Io(IoError::new(ErrorKind::PermissionDenied, message))
+    }
+
+    pub(crate) fn file_url_error(message: &'static str) -> Self {
+        Error::Io(IoError::new(ErrorKind::InvalidInput, message))
+    }
+
+    #[cfg(unix)]
+    pub(crate) fn bad_fd_error() -> Self {
+        Error::Io(IoError::from_raw_os_error(libc::EBADF))
+    }
+
+    #[cfg(unix)]
+    pub(crate) fn fd_socket_error() -> Self {
+        Error::Io(IoError::new(
+            ErrorKind::Unsupported,
+            "A socket can not be opened from its file descriptor, only files, pipes and terminals",
+        ))
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn fd_unsupported_error() -> Self {
+        Error::Io(IoError::new(
+            ErrorKind::Unsupported,
+            "File descriptors can only be given as fd:N on Unix, use ./fd:N for a file with that name",
+        ))
+    }
+
+    pub(crate) fn std_direction_error() -> Self {
+        Error::Io(IoError::new(
+            ErrorKind::Unsupported,
+            "Can only read from stdin and write to stdout or stderr",
+        ))
+    }
+
+    pub(crate) fn data_uri_error(message: &'static str) -> Self {
+        Error::Io(IoError::new(ErrorKind::InvalidInput, message))
+    }
+
+    pub(crate) fn data_uri_write_error() -> Self {
+        Error::Io(IoError::new(
+            ErrorKind::Unsupported,
+            "Can not write to a data url",
+        ))
+    }
+
+    #[cfg(feature = "archive")]
+    pub(crate) fn archive_write_error() -> Self {
+        Error::Io(IoError::new(
+            ErrorKind::Unsupported,
+            "Can not write to a member of an archive",
+        ))
+    }
+
+    #[cfg(feature = "http")]
+    pub(crate) fn conditional_write_error() -> Self {
+        Error::Io(IoError::new(
+            ErrorKind::Unsupported,
+            "Conditional writes are only supported for HTTP urls",
+        ))
+    }
+
+    #[cfg(feature = "http")]
+    pub(crate) fn download_error() -> Self {
+        Error::Io(IoError::new(
+            ErrorKind::Unsupported,
+            "Only a url can be downloaded, and only to a local file",
+        ))
+    }
+
+    #[cfg(feature = "archive-tar")]
+    pub(crate) fn output_dir_error() -> Self {
+        Error::Io(IoError::new(
+            ErrorKind::Unsupported,
+            "An output directory must be a local directory or '-' for a tar stream on stdout",
+        ))
+    }
+
     io_error!(seek_error, ESPIPE, ERROR_BROKEN_PIPE => (Other, "Cannot seek on stream"));
     io_error!(dir_error, EISDIR, ERROR_INVALID_NAME => (PermissionDenied, "Is a directory"));
     io_error!(not_dir_error, ENOTDIR, ERROR_ACCESS_DENIED => (PermissionDenied, "Is not a Directory"));
@@ -0,0 +1,253 @@
+//! Inherited file descriptors named as `fd:3` or `/dev/fd/3`
+//!
+//! As unsafe code is forbidden the descriptor can not be wrapped with `from_raw_fd`, so
+//! `/dev/fd/N` is opened instead, which gives a new handle on the same file, pipe or tty.
+//!
+//! On Linux that handle has an offset of its own, so for a regular file it is moved to where
+//! the parent's descriptor was and given the same append flag, but reading or writing it does
+//! not move the parent's offset. Other Unix systems duplicate the descriptor, sharing its offset.
+//! Sockets can't be opened this way at all so are an error.
+
+use crate::{Error, Result};
+use is_terminal::IsTerminal;
+use std::ffi::{OsStr, OsString};
+use std::fs::{File, Metadata, OpenOptions};
+#[cfg(target_os = "linux")]
+use std::io::{Seek, SeekFrom};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
+
+/// A file descriptor inherited from the parent process
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub(crate) struct FdPath {
+    pub(crate) fd: RawFd,
+    /// the `/dev/fd/N` path that is opened to get a handle on the descriptor
+    dev: PathBuf,
+    /// the argument as it was given
+    arg: OsString,
+}
+
+impl FdPath {
+    /// Parses `fd:N` or `/dev/fd/N`, returning `None` if `path` is neither
+    ///
+    /// `fd:` followed by anything but a number is an error, while `/dev/fd/` followed by
+    /// something else is left to be treated as a normal path.
+    pub(crate) fn parse(path: &OsStr) -> Option<Result<FdPath>> {
+        let arg = path.to_str()?;
+        let fd = match (arg.strip_prefix("fd:"), arg.strip_prefix("/dev/fd/")) {
+            (Some(fd), _) => match parse_fd(fd) {
+                Some(fd) => fd,
+                None => return Some(Err(Error::bad_fd_error())),
+            },
+            (_, Some(fd)) => parse_fd(fd)?,
+            _ => return None,
+        };
+        Some(Ok(FdPath {
+            fd,
+            dev: PathBuf::from(format!("/dev/fd/{fd}")),
+            arg: path.to_owned(),
+        }))
+    }
+
+    pub(crate) fn as_os_str(&self) -> &OsStr {
+        &self.arg
+    }
+
+    /// The `/dev/fd/N` path of the descriptor
+    pub(crate) fn path(&self) -> &Path {
+        &self.dev
+    }
+
+    /// The metadata of the file the descriptor points to, failing if it is not open
+    pub(crate) fn metadata(&self) -> Result<Metadata> {
+        self.dev.metadata().map_err(|_| Error::bad_fd_error())
+    }
+
+    /// Returns an error if the descriptor is not open
+    pub(crate) fn assert_open(&self) -> Result<()> {
+        self.metadata().map(drop)
+    }
+
+    /// Returns true if the descriptor is connected to a terminal
+    pub(crate) fn is_tty(&self) -> bool {
+        match self.fd {
+            0 => std::io::stdin().is_terminal(),
+            1 => std::io::stdout().is_terminal(),
+            2 => std::io::stderr().is_terminal(),
+            // only character devices can be terminals, which also avoids opening pipes
+            _ => match self.metadata() {
+                Ok(meta) if meta.file_type().is_char_device() => File::open(&self.dev)
+                    .map(|file| file.is_terminal())
+                    .unwrap_or(false),
+                _ => false,
+            },
+        }
+    }
+
+    pub(crate) fn open_read(&self) -> Result<File> {
+        self.open(OpenOptions::new().read(true))
+    }
+
+    /// Opens the descriptor for writing, carrying on from where the parent got to
+    pub(crate) fn open_write(&self) -> Result<File> {
+        self.open(OpenOptions::new().write(true))
+    }
+
+    fn open(&self, options: &mut OpenOptions) -> Result<File> {
+        let metadata = self.metadata()?;
+        if metadata.file_type().is_socket() {
+            return Err(Error::fd_socket_error());
+        }
+        #[cfg(target_os = "linux")]
+        let position = self.position();
+        #[cfg(target_os = "linux")]
+        if let Some((_, append)) = position {
+            options.append(append);
+        }
+        #[allow(unused_mut)]
+        let mut file = options.open(&self.dev)?;
+        #[cfg(target_os = "linux")]
+        if let (Some((offset, _)), true) = (position, metadata.is_file()) {
+            file.seek(SeekFrom::Start(offset))?;
+        }
+        Ok(file)
+    }
+
+    /// The offset of the descriptor and whether it appends, from `/proc/self/fdinfo/N`
+    #[cfg(target_os = "linux")]
+    fn position(&self) -> Option<(u64, bool)> {
+        let info = std::fs::read_to_string(format!("/proc/self/fdinfo/{}", self.fd)).ok()?;
+        let field = |name: &str| {
+            info.lines()
+                .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+                .map(str::trim)
+        };
+        let offset = field("pos")?.parse().ok()?;
+        let flags = i32::from_str_radix(field("flags")?, 8).ok()?;
+        Some((offset, flags & libc::O_APPEND != 0))
+    }
+}
+
+fn parse_fd(fd: &str) -> Option<RawFd> {
+    if fd.is_empty() || !fd.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    fd.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClioPath, Input, InputPath, Output, OutputPath};
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        for path in ["fd", "fd.txt", "/dev/fd", "/dev/fd/x", "/dev/fd/-1", "-"] {
+            assert!(FdPath::parse(OsStr::new(path)).is_none(), "{path}");
+        }
+        for path in ["fd:", "fd:x", "fd:-1", "fd:99999999999"] {
+            assert!(FdPath::parse(OsStr::new(path)).unwrap().is_err(), "{path}");
+        }
+        let fd = FdPath::parse(OsStr::new("fd:3")).unwrap()?;
+        assert_eq!(fd.fd, 3);
+        assert_eq!(fd.path(), Path::new("/dev/fd/3"));
+        assert_eq!(fd.as_os_str(), "fd:3");
+        assert_eq!(FdPath::parse(OsStr::new("/dev/fd/12")).unwrap()?.fd, 12);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fd() -> Result<()> {
+        use std::os::unix::io::AsRawFd;
+        let tmp = tempfile::tempdir()?;
+        let file_path = tmp.path().join("data.txt");
+        std::fs::write(&file_path, "from the parent")?;
+
+        let file = File::open(&file_path)?;
+        let arg = format!("fd:{}", file.as_raw_fd());
+        let path = ClioPath::new(&arg)?;
+        assert!(!path.is_local());
+        assert!(!path.is_tty());
+        assert_eq!(path.to_string_lossy(), arg);
+        assert_eq!(
+            path.path(),
+            Path::new(&format!("/dev/fd/{}", file.as_raw_fd()))
+        );
+        let mut input = Input::new(&arg)?;
+        assert_eq!(input.len(), Some(15));
+        let mut contents = String::new();
+        input.read_to_string(&mut contents)?;
+        assert_eq!(contents, "from the parent");
+        InputPath::new(&arg)?;
+
+        let out = OpenOptions::new().append(true).open(&file_path)?;
+        let arg = format!("/dev/fd/{}", out.as_raw_fd());
+        OutputPath::new(&arg)?;
+        let mut output = Output::new(&arg)?;
+        output.write_all(b", and the child")?;
+        output.finish()?;
+        assert_eq!(
+            std::fs::read_to_string(&file_path)?,
+            "from the parent, and the child"
+        );
+
+        // a descriptor part way through a file carries on from there
+        let mut partly_read = File::open(&file_path)?;
+        partly_read.read_exact(&mut [0; 5])?;
+        let mut contents = String::new();
+        Input::new(&format!("fd:{}", partly_read.as_raw_fd()))?.read_to_string(&mut contents)?;
+        assert_eq!(contents, "the parent, and the child");
+
+        let mut overwrite = OpenOptions::new().write(true).open(&file_path)?;
+        overwrite.seek(SeekFrom::Start(4))?;
+        let mut output = Output::new(&format!("fd:{}", overwrite.as_raw_fd()))?;
+        output.write_all(b"THE")?;
+        output.finish()?;
+        assert_eq!(
+            std::fs::read_to_string(&file_path)?,
+            "fromTHEe parent, and the child"
+        );
+
+        // far above any descriptor the tests could have open
+        let closed = "fd:987654";
+        assert!(InputPath::new(closed).is_err());
+        assert!(OutputPath::new(closed).is_err());
+        assert!(Input::new(closed).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fd_pipe() -> Result<()> {
+        use std::os::unix::io::AsRawFd;
+        use std::process::{Command, Stdio};
+        let mut child = Command::new("echo")
+            .arg("from a pipe")
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdout = child.stdout.take().unwrap();
+        let mut input = Input::new(&format!("fd:{}", stdout.as_raw_fd()))?;
+        assert!(!input.can_seek());
+        let mut contents = String::new();
+        input.read_to_string(&mut contents)?;
+        assert_eq!(contents, "from a pipe\n");
+        child.wait()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_fd_socket() -> Result<()> {
+        use std::os::unix::io::AsRawFd;
+        use std::os::unix::net::UnixStream;
+        let (socket, _other) = UnixStream::pair()?;
+        let arg = format!("fd:{}", socket.as_raw_fd());
+        let err = Input::new(&arg).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+        assert_eq!(
+            Output::new(&arg).unwrap_err().kind(),
+            std::io::ErrorKind::Unsupported
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+use crate::{Error, Result};
+use std::ffi::OsStr;
+use std::path::PathBuf;
+
+/// Returns true if `path` looks like a `file:` url rather than a path
+pub(crate) fn is_file_url(path: &OsStr) -> bool {
+    parts(path).is_some()
+}
+
+/// The local path of a `file://` url, or `None` if `path` is not one
+///
+/// The host must be empty or `localhost`, or on Windows the server of a UNC path.
+/// The query and fragment are dropped, and percent encoded bytes are decoded even if
+/// they are not valid UTF-8.
+pub(crate) fn parse(path: &OsStr) -> Option<Result<PathBuf>> {
+    let (host, path) = parts(path)?;
+    Some(to_path(&host, percent_decode(&path)))
+}
+
+/// Splits a `file:` url into its host and still encoded path
+fn parts(path: &OsStr) -> Option<(Vec<u8>, Vec<u8>)> {
+    let bytes = os_bytes(path)?;
+    let scheme = bytes.get(..5)?;
+    if !scheme.eq_ignore_ascii_case(b"file:") {
+        return None;
+    }
+    let rest = &bytes[5..];
+    let (host, path) = match rest.strip_prefix(b"//") {
+        Some(rest) => {
+            let end = rest.iter().position(|b| *b == b'/').unwrap_or(rest.len());
+            rest.split_at(end)
+        }
+        // `file:/path` is also a valid url
+        None if rest.starts_with(b"/") => (&b""[..], rest),
+        None => return None,
+    };
+    let end = path
+        .iter()
+        .position(|b| matches!(b, b'?' | b'#'))
+        .unwrap_or(path.len());
+    Some((host.to_owned(), path[..end].to_owned()))
+}
+
+#[cfg(unix)]
+fn os_bytes(path: &OsStr) -> Option<&[u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Some(path.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn os_bytes(path: &OsStr) -> Option<&[u8]> {
+    path.to_str().map(str::as_bytes)
+}
+
+fn is_local_host(host: &[u8]) -> bool {
+    host.is_empty() || host.eq_ignore_ascii_case(b"localhost")
+}
+
+#[cfg(unix)]
+fn to_path(host: &[u8], path: Vec<u8>) -> Result<PathBuf> {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+    if !is_local_host(host) {
+        return Err(Error::file_url_error(
+            "Only file urls on this machine can be opened",
+        ));
+    }
+    if path.is_empty() {
+        return Ok(PathBuf::from("/"));
+    }
+    Ok(PathBuf::from(OsString::from_vec(path)))
+}
+
+#[cfg(not(unix))]
+fn to_path(host: &[u8], path: Vec<u8>) -> Result<PathBuf> {
+    let invalid = || Error::file_url_error("The path of the file url is not valid UTF-8");
+    let path = String::from_utf8(path).map_err(|_| invalid())?;
+    let host = std::str::from_utf8(host).map_err(|_| invalid())?;
+    let path = path.replace('/', "\\");
+    if !is_local_host(host.as_bytes()) {
+        return Ok(PathBuf::from(format!("\\\\{host}{path}")));
+    }
+    // `/C:/dir` is the drive path `C:\dir`, old urls use `|` instead of `:`
+    let mut chars = path.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('\\'), Some(drive), Some(':' | '|')) if drive.is_ascii_alphabetic() => {
+            Ok(PathBuf::from(format!("{drive}:{}", &path[3..])))
+        }
+        _ => Ok(PathBuf::from(path)),
+    }
+}
+
+/// Decodes `%XX` escapes, leaving any invalid ones as they are
+pub(crate) fn percent_decode<B: AsRef<[u8]> + ?Sized>(value: &B) -> Vec<u8> {
+    let bytes = value.as_ref();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ClioPath;
+    use std::path::Path;
+
+    #[test]
+    fn test_not_file_urls() {
+        for path in [
+            "file",
+            "file:name.txt",
+            "files://x",
+            "-",
+            "/tmp/file:",
+            "fil",
+        ] {
+            assert!(parse(OsStr::new(path)).is_none(), "{path}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_parse() -> Result<()> {
+        let parse = |url: &str| parse(OsStr::new(url)).unwrap();
+        assert_eq!(
+            parse("file:///home/me/data.csv")?,
+            Path::new("/home/me/data.csv")
+        );
+        assert_eq!(
+            parse("FILE://localhost/tmp/a%20b.txt")?,
+            Path::new("/tmp/a b.txt")
+        );
+        assert_eq!(parse("file:/tmp/x?query#fragment")?, Path::new("/tmp/x"));
+        assert_eq!(parse("file:///tmp/100%25%2")?, Path::new("/tmp/100%%2"));
+        assert_eq!(parse("file://")?, Path::new("/"));
+        assert!(parse("file://example.com/etc/passwd").is_err());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8() -> Result<()> {
+        use std::os::unix::ffi::OsStrExt;
+        let path = parse(OsStr::new("file:///tmp/caf%E9")).unwrap()?;
+        assert_eq!(path.as_os_str().as_bytes(), b"/tmp/caf\xe9");
+        let path = parse(OsStr::from_bytes(b"file:///tmp/caf\xe9%20x")).unwrap()?;
+        assert_eq!(path.as_os_str().as_bytes(), b"/tmp/caf\xe9 x");
+        Ok(())
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_parse_windows() -> Result<()> {
+        let parse = |url: &str| parse(OsStr::new(url)).unwrap();
+        assert_eq!(
+            parse("file:///C:/Users/me/a%20b.txt")?,
+            Path::new(r"C:\Users\me\a b.txt")
+        );
+        assert_eq!(parse("file:///c|/x")?, Path::new(r"c:\x"));
+        assert_eq!(
+            parse("file://server/share/x")?,
+            Path::new(r"\\server\share\x")
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_clio_path() -> Result<()> {
+        let url = "file:///tmp/some%20dir/data.csv";
+        let mut path = ClioPath::new(url)?;
+        assert!(path.is_local());
+        assert_eq!(path.file_name(), Some(OsStr::new("data.csv")));
+        assert_eq!(
+            path.to_string_lossy(),
+            Path::new("/tmp/some dir/data.csv").to_string_lossy()
+        );
+
+        path.set_keep_file_url(true);
+        assert_eq!(path.to_string_lossy(), url);
+        assert_eq!(path.as_os_str(), url);
+        assert_eq!(path.clone().to_os_string(), url);
+        // once the path is changed it no longer matches the url
+        path.set_extension("tsv");
+        assert_eq!(
+            path.to_string_lossy(),
+            Path::new("/tmp/some dir/data.tsv").to_string_lossy()
+        );
+
+        let mut path = ClioPath::new("/tmp/data.csv")?;
+        path.set_keep_file_url(true);
+        assert_eq!(path.as_os_str(), "/tmp/data.csv");
+        Ok(())
+    }
+}
@@ -0,0 +1,272 @@
+use crate::http::HttpConfig;
+use crate::{Error, Result};
+use std::borrow::Cow;
+use std::fmt::{self, Debug};
+use std::io::Read;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+static BACKEND: RwLock<Option<Arc<dyn HttpBackend>>> = RwLock::new(None);
+
+/// A HTTP client that sends the requests for urls used as an [`Input`](crate::Input)
+/// or [`Output`](crate::Output)
+///
+/// [ureq](https://docs.rs/ureq), [curl](https://docs.rs/curl) and [reqwest](https://docs.rs/reqwest)
+/// are built in with the `http-ureq`, `http-curl` and `http-reqwest` features,
+/// any other client can be used by implementing this trait and passing it to [`set_http_backend`].
+///
+/// A backend only needs to send a single request and follow any redirects,
+/// retries, range requests and streaming the body of an [`Output`](crate::Output) are all done by clio.
+///
+/// Responses with an error status can either be returned as they are or as an [`Error::Http`],
+/// clio turns the former into the latter. If the connection failed or timed out return the
+/// [`io::Error`](std::io::Error) so the request can be retried, for anything else that stopped
+/// it being sent, e.g. a bad url, return an [`Error::Http`] with code 499, which is not retried.
+pub trait HttpBackend: Send + Sync {
+    /// Sends a `GET` request, returning as soon as the head of the response has arrived,
+    /// leaving the body to be read as it is downloaded
+    fn get(&self, request: &HttpRequest<'_>) -> Result<HttpResponse>;
+
+    /// Sends a request such as a `PUT` with a body read from `body`, `size` bytes long if it is known,
+    /// returning once the whole body has been sent
+    ///
+    /// The body of an [`Output`](crate::Output) is streamed by calling this on another thread
+    /// with `body` reading from a pipe, so it should not be read until the server is ready for it.
+    fn send(
+        &self,
+        request: &HttpRequest<'_>,
+        body: &mut dyn Read,
+        size: Option<u64>,
+    ) -> Result<HttpResponse>;
+
+    /// Sends a `HEAD` request
+    fn head(&self, request: &HttpRequest<'_>) -> Result<HttpResponse>;
+}
+
+/// Use `backend` to send all HTTP requests instead of the built in one
+///
+/// ```no_run
+/// # #[cfg(feature = "http-ureq")] {
+/// clio::set_http_backend(clio::UreqBackend);
+/// # }
+/// ```
+pub fn set_http_backend<B: HttpBackend + 'static>(backend: B) {
+    *BACKEND.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(backend));
+}
+
+/// Swaps the backend set with [`set_http_backend`] for `backend`, returning the old one
+#[cfg(all(
+    test,
+    any(feature = "http-ureq", feature = "http-curl", feature = "http-reqwest")
+))]
+pub(crate) fn replace_backend(
+    backend: Option<Arc<dyn HttpBackend>>,
+) -> Option<Arc<dyn HttpBackend>> {
+    std::mem::replace(
+        &mut *BACKEND.write().unwrap_or_else(|e| e.into_inner()),
+        backend,
+    )
+}
+
+/// The backend to send a request for `url` with, the one set with [`set_http_backend`] or the built in one
+///
+/// `http+unix://` urls always go to clio's own Unix socket client as none of the others support them.
+pub(crate) fn backend(url: &str) -> Result<Arc<dyn HttpBackend>> {
+    #[cfg(unix)]
+    if crate::http::unix::is_unix(url) {
+        return Ok(Arc::new(crate::http::unix::UnixSocketBackend));
+    }
+    if let Some(backend) = &*BACKEND.read().unwrap_or_else(|e| e.into_inner()) {
+        return Ok(backend.clone());
+    }
+    default_backend().ok_or_else(|| {
+        Error::other("No HTTP backend, enable one of the http-* features or call set_http_backend")
+    })
+}
+
+/// The built in backend, ureq is preferred then curl then reqwest if more than one is enabled
+pub(crate) fn default_backend() -> Option<Arc<dyn HttpBackend>> {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "http-ureq")] {
+            Some(Arc::new(crate::http::UreqBackend))
+        } else if #[cfg(feature = "http-curl")] {
+            Some(Arc::new(crate::http::CurlBackend))
+        } else if #[cfg(feature = "http-reqwest")] {
+            Some(Arc::new(crate::http::ReqwestBackend::new()))
+        } else {
+            None
+        }
+    }
+}
+
+/// A request for a [`HttpBackend`] to send
+#[derive(Debug, Clone)]
+pub struct HttpRequest<'a> {
+    method: &'a str,
+    url: Cow<'a, str>,
+    headers: Vec<(String, String)>,
+    config: &'a HttpConfig,
+}
+
+impl<'a> HttpRequest<'a> {
+    /// A request for `url`, an `s3://` url is turned into the url of the object and signed
+    pub(crate) fn new(
+        method: &'a str,
+        url: &'a str,
+        headers: &[(String, String)],
+        config: &'a HttpConfig,
+    ) -> Result<Self> {
+        #[allow(unused_mut)]
+        let mut headers: Vec<_> = config.headers.iter().chain(headers).cloned().collect();
+        #[cfg(feature = "s3")]
+        if crate::http::s3::is_s3(url) {
+            let (url, signature) = crate::http::s3::resolve(method, url, &config.s3)?;
+            headers.extend(signature);
+            return Ok(HttpRequest {
+                method,
+                url: Cow::Owned(url),
+                headers,
+                config,
+            });
+        }
+        Ok(HttpRequest {
+            method,
+            url: Cow::Borrowed(url),
+            headers,
+            config,
+        })
+    }
+
+    /// The method e.g. `GET` or `PUT`
+    pub fn method(&self) -> &str {
+        self.method
+    }
+
+    /// The url to send the request to
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The headers to send, both those from the [`HttpConfig`] and ones for this request such as `Range`
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// How long to wait for the connection to the server to be made
+    pub fn connect_timeout(&self) -> Option<Duration> {
+        self.config.connect_timeout
+    }
+
+    /// How long to wait for more data before giving up
+    pub fn read_timeout(&self) -> Option<Duration> {
+        self.config.read_timeout
+    }
+
+    /// The `User-Agent` header to send instead of the one of the HTTP client
+    pub fn user_agent(&self) -> Option<&str> {
+        self.config.user_agent.as_deref()
+    }
+
+    /// The proxy to send the request through, `None` means connect directly
+    /// as the proxy environment variables have already been taken into account
+    pub fn proxy(&self) -> Option<&str> {
+        self.config.proxy_for(&self.url)
+    }
+
+    /// A PEM file of the certificate authorities to trust instead of the built in ones
+    pub fn ca_bundle(&self) -> Option<&Path> {
+        self.config.ca_bundle.as_deref()
+    }
+}
+
+/// The status, headers and body of a response returned by a [`HttpBackend`]
+pub struct HttpResponse {
+    pub(crate) url: String,
+    pub(crate) status: u16,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: Box<dyn Read + Send>,
+}
+
+impl HttpResponse {
+    /// Creates a response, `url` is where it came from after following any redirects
+    pub fn new<S: Into<String>>(
+        url: S,
+        status: u16,
+        headers: Vec<(String, String)>,
+        body: Box<dyn Read + Send>,
+    ) -> Self {
+        HttpResponse {
+            url: url.into(),
+            status,
+            headers,
+            body,
+        }
+    }
+
+    /// The url the response came from, after following any redirects
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The status code of the response
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// All the headers of the response
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// The value of the first header called `name`, ignoring case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The `Content-Length` header
+    pub(crate) fn content_length(&self) -> Option<u64> {
+        self.header("content-length")
+            .and_then(|length| length.trim().parse().ok())
+    }
+
+    /// The `ETag`, if it is a strong one, or else the `Last-Modified` date,
+    /// to send as `If-Range` when asking for the rest of the body
+    pub(crate) fn validator(&self) -> Option<&str> {
+        match self.header("etag") {
+            // If-Range needs a strong etag
+            Some(etag) if !etag.starts_with("W/") => Some(etag),
+            _ => self.header("last-modified"),
+        }
+    }
+
+    /// The body of the response
+    pub fn into_body(self) -> Box<dyn Read + Send> {
+        self.body
+    }
+
+    /// Turns a response with an error status into an [`Error::Http`]
+    pub(crate) fn error_for_status(self) -> Result<Self> {
+        if self.status >= 400 {
+            Err(Error::Http {
+                code: self.status,
+                message: "the server returned an error".to_owned(),
+            })
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+impl Debug for HttpResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpResponse")
+            .field("url", &self.url)
+            .field("status", &self.status)
+            .field("headers", &self.headers)
+            .finish()
+    }
+}
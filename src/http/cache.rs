@@ -0,0 +1,263 @@
+use crate::http::upload::unique_id;
+use crate::http::{
+    backend, encoding, with_retries, HttpConfig, HttpMetadata, HttpReader, HttpRequest,
+    HttpResponse,
+};
+use crate::{Error, Result};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// What [`open`] found for a url
+pub(crate) enum Cached {
+    /// the body in the cache and the metadata of the response it came from
+    File(File, HttpMetadata),
+    /// a response with `Cache-Control: no-store` or `private`, which is read from the server instead
+    Uncacheable(HttpReader),
+}
+
+/// Opens the url from the cache, first checking with the server that it has not changed
+/// unless the config is offline
+///
+/// Returns `None` if the config has no cache directory.
+pub(crate) fn open(url: &str, config: &Arc<HttpConfig>) -> Result<Option<Cached>> {
+    let dir = match &config.cache_dir {
+        Some(dir) => dir,
+        None if config.offline => return Err(Error::not_found_error()),
+        None => return Ok(None),
+    };
+    let entry = Entry::new(dir, url, config);
+    let cached = entry.load(url, config);
+    if config.offline {
+        return match cached {
+            Some((file, metadata)) => Ok(Some(Cached::File(file, metadata))),
+            None => Err(Error::not_found_error()),
+        };
+    }
+
+    let mut headers: Vec<_> = encoding::accept_encoding(config, false)
+        .into_iter()
+        .collect();
+    if let Some((_, metadata)) = &cached {
+        if let Some(etag) = metadata.etag() {
+            headers.push(("If-None-Match".to_owned(), etag.to_owned()));
+        } else if let Some(last_modified) = metadata.last_modified() {
+            headers.push(("If-Modified-Since".to_owned(), last_modified.to_owned()));
+        }
+    }
+    let resp = with_retries(config, || {
+        backend::backend(url)?
+            .get(&HttpRequest::new("GET", url, &headers, config)?)?
+            .error_for_status()
+    })?;
+    match cached {
+        Some((file, metadata)) if resp.status == 304 => Ok(Some(Cached::File(file, metadata))),
+        _ if !can_store(&resp) => {
+            entry.remove();
+            let reader = HttpReader::from_response(url, config.clone(), resp)?;
+            Ok(Some(Cached::Uncacheable(reader)))
+        }
+        _ => {
+            let (file, metadata) = entry.store(url, config.clone(), resp)?;
+            Ok(Some(Cached::File(file, metadata)))
+        }
+    }
+}
+
+/// Returns false if the response says it must not be kept, or it can't tell which requests it is for
+fn can_store(resp: &HttpResponse) -> bool {
+    let no_store = resp
+        .headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("cache-control"))
+        .flat_map(|(_, value)| value.split(','))
+        .map(|directive| directive.split('=').next().unwrap_or_default().trim())
+        .any(|directive| {
+            directive.eq_ignore_ascii_case("no-store") || directive.eq_ignore_ascii_case("private")
+        });
+    !no_store && !vary(resp).any(|name| name == "*")
+}
+
+/// The names of the request headers that the response depends on, from its `Vary` headers
+fn vary(resp: &HttpResponse) -> impl Iterator<Item = String> + '_ {
+    resp.headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("vary"))
+        .flat_map(|(_, value)| value.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .filter(|name| !name.is_empty())
+}
+
+/// The value of the header `name` that is sent when a url is read with `config`
+fn request_header(config: &HttpConfig, name: &str) -> Option<String> {
+    let accept_encoding = encoding::accept_encoding(config, false);
+    let user_agent = config
+        .user_agent
+        .as_ref()
+        .map(|agent| ("User-Agent".to_owned(), agent.clone()));
+    config
+        .headers
+        .iter()
+        .chain(&accept_encoding)
+        .chain(&user_agent)
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.clone())
+}
+
+/// The length of the cached body of the url, or a [`NotFound`](std::io::ErrorKind::NotFound) error
+pub(crate) fn cached_len(url: &str, config: &HttpConfig) -> Result<u64> {
+    let dir = config
+        .cache_dir
+        .as_ref()
+        .ok_or_else(Error::not_found_error)?;
+    match Entry::new(dir, url, config).load(url, config) {
+        Some((file, _)) => Ok(file.metadata()?.len()),
+        None => Err(Error::not_found_error()),
+    }
+}
+
+/// The files of the cached response to a url
+///
+/// The head names the body file, which gets a new name each time it is stored, so replacing
+/// the head is the one step that swaps in both the new status, headers and body.
+struct Entry {
+    dir: PathBuf,
+    /// the hash the file names start with
+    key: String,
+    /// the status and headers of the response, and the name of the body file
+    head: PathBuf,
+}
+
+impl Entry {
+    /// The entry for `url` as read with the credentials in `config`,
+    /// so a response is never shared with a request that could not have got it
+    fn new(dir: &Path, url: &str, config: &HttpConfig) -> Self {
+        let mut key = url.to_owned();
+        for name in ["authorization", "cookie"] {
+            if let Some(value) = request_header(config, name) {
+                key.push_str(&format!("\n{name}: {value}"));
+            }
+        }
+        #[cfg(feature = "s3")]
+        if let Some(access_key_id) = &config.s3.access_key_id {
+            key.push_str(&format!("\ns3: {access_key_id}"));
+        }
+        let key = format!("{:016x}", fnv1a(key.as_bytes()));
+        Entry {
+            dir: dir.to_owned(),
+            head: dir.join(format!("{key}.head")),
+            key,
+        }
+    }
+
+    /// The path of the body named in the head, if it is one this entry could have stored
+    fn body_path(&self, name: &str) -> Option<PathBuf> {
+        let version = name.strip_prefix(&self.key)?.strip_prefix('.')?;
+        let valid =
+            !version.is_empty() && version.chars().all(|c| c.is_ascii_hexdigit() || c == '-');
+        valid.then(|| self.dir.join(name))
+    }
+
+    /// The path of the body that the head currently names
+    fn body(&self) -> Option<PathBuf> {
+        let mut lines = BufReader::new(File::open(&self.head).ok()?).lines();
+        lines.next()?.ok()?;
+        self.body_path(&lines.next()?.ok()?)
+    }
+
+    /// The cached body and the metadata of the response it came from, if there is one
+    /// for this url that was sent the same values of the headers it varies by
+    fn load(&self, url: &str, config: &HttpConfig) -> Option<(File, HttpMetadata)> {
+        let mut lines = BufReader::new(File::open(&self.head).ok()?).lines();
+        // entries are found by a hash so check it really is this url
+        if lines.next()?.ok()? != url {
+            return None;
+        }
+        let body = self.body_path(&lines.next()?.ok()?)?;
+        let resp_url = lines.next()?.ok()?;
+        let status = lines.next()?.ok()?.parse().ok()?;
+        // the response headers, then after a blank line the request headers it varies by
+        let (mut headers, mut sent) = (Vec::new(), Vec::new());
+        let mut after_blank = false;
+        for line in lines {
+            let line = line.ok()?;
+            if line.is_empty() {
+                after_blank = true;
+                continue;
+            }
+            let (name, value) = line.split_once(": ")?;
+            let list = if after_blank { &mut sent } else { &mut headers };
+            list.push((name.to_owned(), value.to_owned()));
+        }
+        let resp = HttpResponse::new(resp_url, status, headers, Box::new(io::empty()));
+        for name in vary(&resp) {
+            let was_sent = sent.iter().find(|(sent, _)| *sent == name);
+            if was_sent.map(|(_, value)| value.clone()) != request_header(config, &name) {
+                return None;
+            }
+        }
+        Some((File::open(body).ok()?, HttpMetadata::new(&resp)))
+    }
+
+    /// Downloads the body of the response into the cache
+    ///
+    /// The body is written to a new file and then the head naming it is written to a
+    /// temporary file and renamed over the old one, so a download that fails part way never
+    /// leaves a broken entry, and the head is never seen with another response's body.
+    fn store(
+        &self,
+        url: &str,
+        config: Arc<HttpConfig>,
+        resp: HttpResponse,
+    ) -> Result<(File, HttpMetadata)> {
+        fs::create_dir_all(&self.dir)?;
+        let body_name = format!("{}.{}", self.key, unique_id());
+        let mut head = format!("{url}\n{body_name}\n{}\n{}\n", resp.url, resp.status);
+        for (name, value) in &resp.headers {
+            head.push_str(&format!("{name}: {value}\n"));
+        }
+        head.push('\n');
+        for name in vary(&resp) {
+            if let Some(value) = request_header(&config, &name) {
+                head.push_str(&format!("{name}: {value}\n"));
+            }
+        }
+
+        let mut reader = HttpReader::from_response(url, config, resp)?;
+        let mut body = tempfile::NamedTempFile::new_in(&self.dir)?;
+        io::copy(&mut reader, &mut body)?;
+        body.as_file().sync_data()?;
+        let mut file = body.persist_noclobber(self.dir.join(&body_name))?;
+
+        let old_body = self.body();
+        let mut head_file = tempfile::NamedTempFile::new_in(&self.dir)?;
+        head_file.write_all(head.as_bytes())?;
+        head_file.as_file().sync_data()?;
+        head_file.persist(&self.head)?;
+        // anything still reading the old body keeps its open file
+        if let Some(old_body) = old_body {
+            let _ = fs::remove_file(old_body);
+        }
+
+        file.rewind()?;
+        Ok((file, reader.metadata().clone()))
+    }
+
+    /// Deletes the entry, so that a response that must not be kept replaces an old one
+    fn remove(&self) {
+        let body = self.body();
+        let _ = fs::remove_file(&self.head);
+        if let Some(body) = body {
+            let _ = fs::remove_file(body);
+        }
+    }
+}
+
+/// The 64 bit FNV-1a hash, which unlike [`DefaultHasher`](std::collections::hash_map::DefaultHasher)
+/// stays the same between versions of rust so old entries can still be found
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
@@ -1,194 +1,278 @@
-use curl::easy::{Easy, ReadError};
+use crate::http::{HttpBackend, HttpRequest, HttpResponse};
+use curl::easy::{Easy, List, ReadError};
 use curl::Error;
-use pipe::{PipeBufWriter, PipeReader};
-use std::convert::TryFrom;
-use std::fmt::{self, Debug};
-use std::io::{Read, Write};
-use std::sync::atomic::{AtomicI64, Ordering};
+use pipe::PipeReader;
+use std::io::{self, Cursor, Read, Write};
 use std::sync::mpsc::{sync_channel, Receiver};
 use std::sync::{Arc, Mutex};
 use std::thread::spawn;
+use url::Url;
 
-pub struct HttpWriter {
-    write: PipeBufWriter,
-    rx: Mutex<Receiver<Result<(), Error>>>,
+/// The status line and headers of the response, and the url it came from after following any redirects
+#[derive(Default)]
+struct Head {
+    url: String,
+    status: u16,
+    headers: Vec<(String, String)>,
 }
 
-impl HttpWriter {
-    pub fn new(url: &str, size: Option<u64>) -> Result<Self, Error> {
-        let mut easy = new_easy(url)?;
-
-        let (mut read, write) = pipe::pipe_buffered();
-
-        let (done_tx, rx) = sync_channel(0);
-        let connected_tx = done_tx.clone();
-
-        let mut connected = false;
-
-        easy.put(true)?;
-        easy.upload(true)?;
-        if let Some(size) = size {
-            easy.in_filesize(size)?;
+impl Head {
+    fn new(url: &str) -> Self {
+        Head {
+            url: url.to_owned(),
+            ..Head::default()
         }
-        easy.read_function(move |into| {
-            if !connected {
-                connected_tx.send(Ok(())).map_err(|_| ReadError::Abort)?;
-                connected = true;
-            }
-            let len = read.read(into).unwrap();
-            eprintln!("read: {len}");
-            Ok(len)
-        })?;
-        spawn(move || {
-            done_tx.send(easy.perform()).unwrap();
-        });
-
-        rx.recv().unwrap()?;
-        let rx = Mutex::new(rx);
-
-        Ok(HttpWriter { write, rx })
     }
 
-    pub fn finish(self) -> Result<(), Error> {
-        drop(self.write);
-        self.rx
-            .try_lock()
-            .expect("clio HttpReader lock should one ever be taken once while dropping")
-            .recv()
-            .unwrap()?;
-        Ok(())
-    }
-}
-
-impl Write for HttpWriter {
-    fn write(&mut self, buffer: &[u8]) -> Result<usize, std::io::Error> {
-        self.write.write(buffer)
-    }
-    fn flush(&mut self) -> Result<(), std::io::Error> {
-        self.write.flush()
-    }
-}
-
-impl fmt::Debug for HttpWriter {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("HttpWriter").finish()
+    fn into_response(self, body: Box<dyn Read + Send>) -> HttpResponse {
+        HttpResponse {
+            url: self.url,
+            status: self.status,
+            headers: self.headers,
+            body,
+        }
     }
 }
 
-pub struct HttpReader {
-    length: Option<u64>,
-    read: PipeReader,
-    rx: Mutex<Receiver<Result<(), Error>>>,
-}
-
-impl HttpReader {
-    pub fn new(url: &str) -> Result<Self, Error> {
-        let url = url.to_owned();
+/// The [`HttpBackend`] using [curl](https://docs.rs/curl)
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CurlBackend;
 
+impl HttpBackend for CurlBackend {
+    /// The transfer runs on its own thread and the body is streamed back through a pipe.
+    fn get(&self, request: &HttpRequest<'_>) -> crate::Result<HttpResponse> {
         let (read, mut write) = pipe::pipe();
 
         let (done_tx, rx) = sync_channel(0);
         let connected_tx = done_tx.clone();
 
         let mut connected = false;
-        let length = Arc::new(AtomicI64::new(-1));
+        let head = Arc::new(Mutex::new(Head::new(request.url())));
 
-        let mut easy = new_easy(&url)?;
+        let mut easy = new_easy(request)?;
         easy.header_function({
-            let length = length.clone();
-            move |data| {
-                let data = std::str::from_utf8(data).unwrap().to_lowercase();
-                if let Some(length_string) = data.strip_prefix("content-length:") {
-                    length.store(
-                        length_string.trim().parse::<i64>().unwrap_or(-1),
-                        Ordering::Relaxed,
-                    );
-                }
-                if data.starts_with("http/") {
-                    length.store(-1, Ordering::Relaxed);
-                }
+            let head = head.clone();
+            move |line| {
+                parse_header(&mut head.lock().unwrap_or_else(|e| e.into_inner()), line);
                 true
             }
         })?;
 
-        easy.write_function({
-            let length = length.clone();
-            move |data| {
-                if !connected {
-                    if data.is_empty() {
-                        length.store(-1, Ordering::Relaxed);
-                    }
-                    if connected_tx.send(Ok(())).is_err() {
-                        // if the message queue is broken return 0 to curl to indicate a problem
-                        return Ok(0);
-                    }
-                    connected = true;
-                }
-
-                if write.write_all(data).is_err() {
-                    // if the pipe is broken return 0 to curl to indicate a problem
+        easy.write_function(move |data| {
+            if !connected {
+                if connected_tx.send(Ok(())).is_err() {
+                    // if the message queue is broken return 0 to curl to indicate a problem
                     return Ok(0);
                 }
-                Ok(data.len())
+                connected = true;
+            }
+
+            if write.write_all(data).is_err() {
+                // if the pipe is broken return 0 to curl to indicate a problem
+                return Ok(0);
             }
+            Ok(data.len())
         })?;
 
         spawn(move || {
-            let err = easy.perform();
+            let result = easy.perform().map_err(|err| perform_error(&mut easy, err));
             drop(easy);
-            done_tx.send(err).unwrap();
+            let _ = done_tx.send(result);
         });
 
-        rx.recv().unwrap()?;
-        let rx = Mutex::new(rx);
+        rx.recv().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::Other,
+                "the curl thread stopped without a result",
+            )
+        })??;
 
-        let length = u64::try_from(length.load(Ordering::Relaxed)).ok();
-        Ok(HttpReader { length, read, rx })
+        let head = std::mem::take(&mut *head.lock().unwrap_or_else(|e| e.into_inner()));
+        Ok(head.into_response(Box::new(CurlBody {
+            read,
+            rx: Mutex::new(Some(rx)),
+        })))
     }
 
-    pub fn len(&self) -> Option<u64> {
-        self.length
+    fn send(
+        &self,
+        request: &HttpRequest<'_>,
+        body: &mut dyn Read,
+        size: Option<u64>,
+    ) -> crate::Result<HttpResponse> {
+        let mut easy = new_easy(request)?;
+        easy.upload(true)?;
+        if request.method() != "PUT" {
+            easy.custom_request(request.method())?;
+        }
+        if let Some(size) = size {
+            easy.in_filesize(size)?;
+        }
+
+        let mut head = Head::new(request.url());
+        let mut data = Vec::new();
+        let result = {
+            let mut transfer = easy.transfer();
+            transfer.read_function(|into| body.read(into).map_err(|_| ReadError::Abort))?;
+            transfer.header_function(|line| {
+                parse_header(&mut head, line);
+                true
+            })?;
+            transfer.write_function(|chunk| {
+                data.extend_from_slice(chunk);
+                Ok(chunk.len())
+            })?;
+            transfer.perform()
+        };
+        result.map_err(|err| perform_error(&mut easy, err))?;
+
+        Ok(head.into_response(Box::new(Cursor::new(data))))
     }
 
-    #[allow(dead_code)]
-    pub fn finish(self) -> Result<(), Error> {
-        drop(self.read);
-        self.rx
-            .try_lock()
-            .expect("clio HttpWriter lock should one ever be taken once while dropping")
-            .recv()
-            .unwrap()?;
+    fn head(&self, request: &HttpRequest<'_>) -> crate::Result<HttpResponse> {
+        let mut easy = new_easy(request)?;
+        easy.nobody(true)?;
+
+        let mut head = Head::new(request.url());
+        let result = {
+            let mut transfer = easy.transfer();
+            transfer.header_function(|line| {
+                parse_header(&mut head, line);
+                true
+            })?;
+            transfer.perform()
+        };
+        result.map_err(|err| perform_error(&mut easy, err))?;
+
+        Ok(head.into_response(Box::new(io::empty())))
+    }
+}
+
+/// The body of a response being streamed from the thread running the transfer
+struct CurlBody {
+    read: PipeReader,
+    /// gets the result of the transfer once the body has been read,
+    /// `None` once that has been checked
+    rx: Mutex<Option<Receiver<crate::Result<()>>>>,
+}
+
+impl CurlBody {
+    /// Waits for the transfer to finish, returning any error that happened after the first byte
+    fn finish(&mut self) -> crate::Result<()> {
+        if let Some(rx) = self.rx.get_mut().unwrap_or_else(|e| e.into_inner()).take() {
+            // if the channel is closed the result was already received before the body started
+            if let Ok(result) = rx.recv() {
+                result?;
+            }
+        }
         Ok(())
     }
 }
 
-impl Read for HttpReader {
+impl Read for CurlBody {
     fn read(&mut self, buffer: &mut [u8]) -> Result<usize, std::io::Error> {
-        self.read.read(buffer)
+        let len = self.read.read(buffer)?;
+        if len == 0 && !buffer.is_empty() {
+            self.finish()?;
+        }
+        Ok(len)
     }
 }
 
-impl Debug for HttpReader {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("HttpReader").finish()
+/// Adds a line from the head of the response to the status and headers
+fn parse_header(head: &mut Head, line: &[u8]) {
+    let line = String::from_utf8_lossy(line);
+    if line.starts_with("HTTP/") {
+        // the start of a new response, e.g. after a redirect
+        if (300..400).contains(&head.status) {
+            let location = head
+                .headers
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("location"));
+            if let Some((_, location)) = location {
+                if let Ok(url) = Url::parse(&head.url).and_then(|url| url.join(location)) {
+                    head.url = url.into();
+                }
+            }
+        }
+        head.status = line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse().ok())
+            .unwrap_or(0);
+        head.headers.clear();
+    } else if let Some((name, value)) = line.split_once(':') {
+        head.headers
+            .push((name.trim().to_owned(), value.trim().to_owned()));
+    }
+}
+
+/// Converts an error from curl, using the status code of the response if there was one
+fn perform_error(easy: &mut Easy, err: Error) -> crate::Error {
+    if err.is_partial_file() {
+        return crate::Error::Io(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            err.description(),
+        ));
+    }
+    match easy.response_code() {
+        Ok(code) if err.is_http_returned_error() => crate::Error::Http {
+            code: code as u16,
+            message: err.description().to_owned(),
+        },
+        _ => err.into(),
     }
 }
 
-fn new_easy(url: &str) -> Result<Easy, Error> {
+fn new_easy(request: &HttpRequest<'_>) -> Result<Easy, Error> {
     let mut easy = Easy::new();
-    easy.url(url)?;
+    easy.url(request.url())?;
     easy.follow_location(true)?;
     easy.fail_on_error(true)?;
 
+    let mut list = List::new();
+    for (name, value) in request.headers() {
+        list.append(&format!("{name}: {value}"))?;
+    }
+    easy.http_headers(list)?;
+    if let Some(timeout) = request.connect_timeout() {
+        easy.connect_timeout(timeout)?;
+    }
+    if let Some(timeout) = request.read_timeout() {
+        // give up if less than a byte a second is sent for the whole timeout
+        easy.low_speed_limit(1)?;
+        easy.low_speed_time(timeout)?;
+    }
+    if let Some(user_agent) = request.user_agent() {
+        easy.useragent(user_agent)?;
+    }
+    // an empty proxy stops curl from reading the environment itself, as the config already has
+    easy.proxy(request.proxy().unwrap_or(""))?;
+    if let Some(ca_bundle) = request.ca_bundle() {
+        easy.cainfo(ca_bundle)?;
+    }
+
     Ok(easy)
 }
 
 #[cfg(feature = "http")]
 impl From<Error> for crate::Error {
     fn from(err: Error) -> Self {
-        crate::Error::Http {
-            code: 499,
-            message: err.description().to_owned(),
-        }
+        // only failures of the connection itself are worth retrying
+        let kind = if err.is_couldnt_connect() {
+            io::ErrorKind::ConnectionRefused
+        } else if err.is_operation_timedout() {
+            io::ErrorKind::TimedOut
+        } else if err.is_send_error() || err.is_recv_error() || err.is_got_nothing() {
+            io::ErrorKind::ConnectionReset
+        } else if err.is_partial_file() {
+            io::ErrorKind::UnexpectedEof
+        } else {
+            return crate::Error::Http {
+                code: 499,
+                message: err.description().to_owned(),
+            };
+        };
+        crate::Error::Io(io::Error::new(kind, err.description()))
     }
 }
@@ -0,0 +1,191 @@
+use crate::http::{backend, encoding, truncated_error, with_retries, HttpConfig, HttpRequest};
+use crate::path::ClioPathEnum;
+use crate::{ClioPath, Error, Output, OutputPath, Result};
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Downloads the url `source` to the local file `dest`, resuming where a previous attempt stopped
+///
+/// The body is written to a temporary file next to `dest` which is renamed to `dest` once it is
+/// complete, just like an atomic [`Output`], so `dest` is never left half written.
+/// If the download is interrupted, either by an error or the process being killed, calling this
+/// again requests the rest of the body with `Range` and `If-Range`, starting again from the
+/// beginning if the file changed on the server in the meantime.
+/// Broken downloads are also resumed up to [`HttpConfig::retries`] times before giving up.
+///
+/// The temporary file is called `<dest>.<validator>.part`, where the validator is the hex encoded
+/// `ETag` or `Last-Modified` date of the response, so the two can never get out of step.
+/// A response with neither can't be resumed so is not kept if the download fails.
+///
+/// The size of the finished file is checked against the `Content-Length`, and the number of
+/// bytes in it returned.
+///
+/// ```no_run
+/// use clio::{ClioPath, OutputPath};
+///
+/// let source = ClioPath::new("https://example.com/big.iso")?;
+/// let dest = OutputPath::new("big.iso")?;
+/// clio::download(&source, &dest)?;
+/// # Ok::<(), clio::Error>(())
+/// ```
+pub fn download(source: &ClioPath, dest: &OutputPath) -> Result<u64> {
+    let url = match &source.path {
+        ClioPathEnum::Http(url) => url.as_str(),
+        _ => return Err(Error::download_error()),
+    };
+    let mut dest = dest.path().clone();
+    if !dest.is_local() {
+        return Err(Error::download_error());
+    }
+    // the file is saved as it is on the server, not compressed again
+    dest.raw = true;
+    dest.atomic = true;
+    let config = source.http_config();
+    with_retries(&config, || resume(url, &dest, &config))
+}
+
+/// Downloads the rest of the body, returning the size of the whole file
+fn resume(url: &str, dest: &ClioPath, config: &HttpConfig) -> Result<u64> {
+    let partial = Partial::find(dest.path())?;
+    let start = partial.as_ref().map_or(0, |partial| partial.len);
+    // the file is saved as it is on the server, not compressed for the transfer
+    let mut headers: Vec<_> = encoding::accept_encoding(config, true)
+        .into_iter()
+        .collect();
+    if let (Some(partial), true) = (&partial, start > 0) {
+        headers.push(("Range".to_owned(), format!("bytes={start}-")));
+        headers.push(("If-Range".to_owned(), partial.validator.clone()));
+    }
+    let resp = backend::backend(url)?
+        .get(&HttpRequest::new("GET", url, &headers, config)?)?
+        .error_for_status();
+    let resp = match (resp, &partial) {
+        // the server has nothing after `start`, so the file can't be the one that was started
+        (Err(Error::Http { code: 416, .. }), Some(partial)) if start > 0 => {
+            fs::remove_file(&partial.path)?;
+            return resume(url, dest, config);
+        }
+        (resp, _) => resp?,
+    };
+
+    let (start, size, part) = if resp.status == 206 {
+        let total = resp
+            .header("content-range")
+            .and_then(|range| range.trim().strip_prefix(&format!("bytes {start}-")))
+            .ok_or_else(|| Error::other("the server sent a different range to the one asked for"))?
+            .rsplit('/')
+            .next()
+            .and_then(|total| total.parse().ok());
+        let size = total.or_else(|| Some(start + resp.content_length()?));
+        // only a partial download has a validator to send with a range
+        (start, size, partial.map(|partial| partial.path))
+    } else {
+        // the whole file was sent, either because it changed or ranges are not supported
+        if let Some(partial) = partial {
+            fs::remove_file(&partial.path)?;
+        }
+        let part = resp
+            .validator()
+            .map(|validator| Partial::path_for(dest.path(), validator));
+        (0, resp.content_length(), part)
+    };
+
+    let mut output = match &part {
+        Some(part) => Output::resume_atomic(dest.clone(), part)?,
+        None => Output::new(dest.clone())?,
+    };
+    // a body without a validator can't be resumed so it is thrown away with the output
+    let keep = |output: Output| match part {
+        Some(_) => output.keep_partial(),
+        None => Ok(()),
+    };
+    let received = match io::copy(&mut resp.into_body(), &mut output) {
+        Ok(received) => start + received,
+        Err(err) => {
+            keep(output)?;
+            return Err(err.into());
+        }
+    };
+    match size {
+        Some(size) if received < size => {
+            keep(output)?;
+            Err(truncated_error(received, size))
+        }
+        // the partial file is no use so it is deleted when the output is dropped
+        Some(size) if received > size => {
+            Err(Error::other("the server sent more than its Content-Length"))
+        }
+        _ => {
+            output.flush()?;
+            if let Some(file) = output.get_file() {
+                file.sync_data()?;
+            }
+            output.finish()?;
+            Ok(received)
+        }
+    }
+}
+
+/// The partly downloaded body of a url, named after the validator of the response it came from
+struct Partial {
+    path: PathBuf,
+    /// the `ETag` or `Last-Modified` date to send as `If-Range`
+    validator: String,
+    len: u64,
+}
+
+impl Partial {
+    /// The temporary file for the body of a response with this validator
+    fn path_for(dest: &Path, validator: &str) -> PathBuf {
+        let mut name = OsString::from(dest.as_os_str());
+        name.push(format!(".{}.part", hex_encode(validator.as_bytes())));
+        PathBuf::from(name)
+    }
+
+    /// Looks for a partial download of `dest` left by an earlier attempt
+    fn find(dest: &Path) -> Result<Option<Partial>> {
+        let (dir, name) = match (dest.parent(), dest.file_name().and_then(|n| n.to_str())) {
+            (Some(dir), Some(name)) => (dir, name),
+            _ => return Ok(None),
+        };
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let validator = entry
+                .file_name()
+                .to_str()
+                .and_then(|file| file.strip_prefix(name)?.strip_prefix('.'))
+                .and_then(|file| file.strip_suffix(".part"))
+                .and_then(hex_decode);
+            if let Some(validator) = validator {
+                return Ok(Some(Partial {
+                    path: entry.path(),
+                    validator,
+                    len: entry.metadata()?.len(),
+                }));
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn hex_decode(hex: &str) -> Option<String> {
+    if hex.is_empty() || hex.len() % 2 != 0 {
+        return None;
+    }
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    String::from_utf8(bytes).ok()
+}
@@ -0,0 +1,56 @@
+use crate::http::{HttpConfig, HttpResponse};
+use crate::{Error, Result};
+use flate2::read::{MultiGzDecoder, ZlibDecoder};
+use std::io::{Error as IoError, ErrorKind, Read};
+
+/// The `Content-Encoding`s that [`decode`] can undo
+const SUPPORTED: &str = "gzip, deflate, br";
+
+/// The `Accept-Encoding` header to send with a `GET`, unless the config already has one
+///
+/// A range request asks for the body as it is, so the range is of the bytes of the file
+/// rather than the bytes of a compressed copy of it.
+pub(crate) fn accept_encoding(config: &HttpConfig, ranged: bool) -> Option<(String, String)> {
+    if config
+        .headers
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case("accept-encoding"))
+    {
+        return None;
+    }
+    let value = if ranged { "identity" } else { SUPPORTED };
+    Some(("Accept-Encoding".to_owned(), value.to_owned()))
+}
+
+/// Returns true if the body of the response has to be decoded, so its `Content-Length`
+/// is not the length of the file
+pub(crate) fn is_encoded(resp: &HttpResponse) -> bool {
+    resp.header("content-encoding").map_or(false, |encoding| {
+        encoding
+            .split(',')
+            .any(|coding| !coding.trim().eq_ignore_ascii_case("identity"))
+    })
+}
+
+/// Undoes each of the comma separated encodings in a `Content-Encoding` header, last one first
+pub(crate) fn decode(
+    encoding: Option<&str>,
+    mut body: Box<dyn Read + Send>,
+) -> Result<Box<dyn Read + Send>> {
+    for coding in encoding.unwrap_or_default().rsplit(',') {
+        let coding = coding.trim().to_ascii_lowercase();
+        body = match coding.as_str() {
+            "" | "identity" => body,
+            "gzip" | "x-gzip" => Box::new(MultiGzDecoder::new(body)),
+            "deflate" => Box::new(ZlibDecoder::new(body)),
+            "br" => Box::new(brotli_decompressor::Decompressor::new(body, 4096)),
+            _ => {
+                return Err(Error::Io(IoError::new(
+                    ErrorKind::Unsupported,
+                    format!("unsupported Content-Encoding {coding}"),
+                )))
+            }
+        };
+    }
+    Ok(body)
+}
@@ -1,15 +1,55 @@
+mod backend;
+mod cache;
+mod config;
 #[cfg(feature = "http-curl")]
 mod curl;
-#[cfg(feature = "http-curl")]
-pub use self::curl::*;
-
+mod download;
+mod encoding;
+#[cfg(feature = "http-reqwest")]
+mod reqwest;
+#[cfg(feature = "s3")]
+mod s3;
+#[cfg(unix)]
+mod unix;
+mod upload;
 #[cfg(feature = "http-ureq")]
 mod ureq;
+mod webdav;
+
+#[cfg(all(
+    test,
+    any(feature = "http-ureq", feature = "http-curl", feature = "http-reqwest")
+))]
+pub(crate) mod test_server;
+
+pub use self::backend::{set_http_backend, HttpBackend, HttpRequest, HttpResponse};
+pub(crate) use self::cache::{open as open_cached, Cached};
+pub use self::config::HttpConfig;
+#[cfg(feature = "http-curl")]
+pub use self::curl::CurlBackend;
+pub use self::download::download;
+#[cfg(feature = "http-reqwest")]
+pub use self::reqwest::ReqwestBackend;
+#[cfg(feature = "s3")]
+pub(crate) use self::s3::list as s3_list;
+pub use self::upload::Method;
+pub(crate) use self::upload::Upload;
 #[cfg(feature = "http-ureq")]
-pub use self::ureq::*;
+pub use self::ureq::UreqBackend;
+pub(crate) use self::webdav::list as webdav_list;
 
+use crate::file_url::percent_decode;
 use crate::{Error, Result};
-use std::ffi::OsStr;
+use pipe::{PipeBufWriter, PipeReader};
+use std::ffi::{OsStr, OsString};
+use std::fmt::{self, Debug};
+use std::io::{self, Error as IoError, ErrorKind, Read, Result as IoResult, Seek, SeekFrom, Write};
+#[cfg(feature = "clap-parse")]
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{sleep, spawn};
 use url::Url;
 
 pub(crate) fn try_to_url(url: &OsStr) -> Result<Url> {
@@ -25,5 +65,1798 @@ pub(crate) fn try_to_url(url: &OsStr) -> Result<Url> {
 
 pub(crate) fn is_http(url: &OsStr) -> bool {
     let url = url.to_string_lossy();
+    #[cfg(feature = "s3")]
+    if s3::is_s3(&url) {
+        return true;
+    }
+    #[cfg(unix)]
+    if unix::is_unix(&url) {
+        return true;
+    }
     url.starts_with("http://") || url.starts_with("https://")
 }
+
+/// Where a request for a url is really sent
+#[cfg(feature = "clap-parse")]
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum RequestTarget {
+    /// A server on the network, and whether it is reached with https
+    Host { https: bool, host: String },
+    /// A Unix domain socket on this machine, so nothing leaves it
+    Socket(PathBuf),
+}
+
+/// Finds where a request for `url` is sent, which for an `s3://` url is the endpoint
+/// rather than the bucket in the url, and for a `http+unix://` url is the socket
+#[cfg(feature = "clap-parse")]
+#[cfg_attr(not(feature = "s3"), allow(unused_variables))]
+pub(crate) fn request_target(url: &Url, config: &HttpConfig) -> Result<RequestTarget> {
+    #[cfg(feature = "s3")]
+    if s3::is_s3(url.as_str()) {
+        return request_target(&s3::endpoint_url(url, &config.s3)?, config);
+    }
+    #[cfg(unix)]
+    if unix::is_unix(url.as_str()) {
+        return Ok(RequestTarget::Socket(unix::socket_path(url)));
+    }
+    Ok(RequestTarget::Host {
+        https: url.scheme() == "https",
+        host: url.host_str().unwrap_or_default().to_owned(),
+    })
+}
+
+/// Returns true if the `CLIO_OFFLINE` environment variable is set to anything but `0`,
+/// which stops any path being a url
+pub(crate) fn network_disabled() -> bool {
+    is_offline_value(std::env::var_os("CLIO_OFFLINE"))
+}
+
+fn is_offline_value(value: Option<OsString>) -> bool {
+    value.map_or(false, |value| !value.is_empty() && value != "0")
+}
+
+/// Sends a GET request with the backend, asking for the body from `start` onwards if it is `Some`
+fn get(
+    url: &str,
+    start: Option<u64>,
+    if_range: Option<&str>,
+    config: &HttpConfig,
+) -> Result<HttpResponse> {
+    let mut headers: Vec<_> = start
+        .map(|start| ("Range".to_owned(), format!("bytes={start}-")))
+        .into_iter()
+        .chain(encoding::accept_encoding(config, start.is_some()))
+        .collect();
+    if let (Some(_), Some(validator)) = (start, if_range) {
+        headers.push(("If-Range".to_owned(), validator.to_owned()));
+    }
+    backend::backend(url)?
+        .get(&HttpRequest::new("GET", url, &headers, config)?)?
+        .error_for_status()
+}
+
+/// Sends a request with a body read from `body` with the backend
+fn send(
+    method: &str,
+    url: &str,
+    headers: &[(String, String)],
+    body: &mut dyn Read,
+    size: Option<u64>,
+    config: &HttpConfig,
+) -> Result<HttpResponse> {
+    backend::backend(url)?
+        .send(&HttpRequest::new(method, url, headers, config)?, body, size)?
+        .error_for_status()
+}
+
+/// Details of the response to the `GET` request of an [`Input`](crate::Input) from a url
+///
+/// Returned by [`Input::http_metadata`](crate::Input::http_metadata).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct HttpMetadata {
+    url: String,
+    status: u16,
+    content_type: Option<String>,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    filename: Option<String>,
+}
+
+impl HttpMetadata {
+    fn new(resp: &HttpResponse) -> Self {
+        let header = |name| resp.header(name).map(str::to_owned);
+        HttpMetadata {
+            url: resp.url.clone(),
+            status: resp.status,
+            content_type: header("content-type"),
+            etag: header("etag"),
+            last_modified: header("last-modified"),
+            filename: resp
+                .header("content-disposition")
+                .and_then(content_disposition_filename),
+        }
+    }
+
+    /// The url the response came from, after following any redirects
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The status code of the response
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// The `Content-Type` header, e.g. `text/html; charset=utf-8`
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    /// The `ETag` header, including the quotes
+    pub fn etag(&self) -> Option<&str> {
+        self.etag.as_deref()
+    }
+
+    /// The `Last-Modified` header, e.g. `Wed, 21 Oct 2015 07:28:00 GMT`
+    pub fn last_modified(&self) -> Option<&str> {
+        self.last_modified.as_deref()
+    }
+
+    /// The file name from the `Content-Disposition` header,
+    /// with any directories removed so it is safe to use as a local file name
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+}
+
+/// Finds the `filename*` or `filename` parameter of a `Content-Disposition` header
+fn content_disposition_filename(header: &str) -> Option<String> {
+    let mut filename = None;
+    for param in header.split(';').skip(1) {
+        let (name, value) = match param.split_once('=') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => continue,
+        };
+        if name.eq_ignore_ascii_case("filename*") {
+            // RFC 5987 e.g. UTF-8'en'na%C3%AFve.txt, this is preferred over `filename`
+            let mut parts = value.splitn(3, '\'');
+            let charset = parts.next()?;
+            let value = parts.nth(1)?;
+            let bytes = percent_decode(value);
+            let decoded = if charset.eq_ignore_ascii_case("utf-8") {
+                String::from_utf8(bytes).ok()
+            } else {
+                // ISO-8859-1 maps each byte to the same code point
+                Some(bytes.into_iter().map(char::from).collect())
+            };
+            if decoded.is_some() {
+                filename = decoded;
+                break;
+            }
+        } else if name.eq_ignore_ascii_case("filename") && filename.is_none() {
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .map_or_else(|| value.to_owned(), |v| v.replace("\\\"", "\""));
+            filename = Some(value);
+        }
+    }
+    let filename = filename?;
+    let filename = filename.rsplit(['/', '\\']).next()?;
+    match filename {
+        "" | "." | ".." => None,
+        _ => Some(filename.to_owned()),
+    }
+}
+
+/// Replaces the entities and character references in the text of an XML element
+fn xml_unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.find(';') {
+            Some(end) => end,
+            None => break,
+        };
+        let entity = &rest[1..end];
+        let char = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(|code| code.ok())
+                .and_then(char::from_u32),
+        };
+        match char {
+            Some(char) => {
+                result.push(char);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Returns true if the request might work if it is sent again
+fn is_retryable(err: &Error) -> bool {
+    match err {
+        Error::Http { code, .. } => matches!(code, 408 | 429 | 500 | 502 | 503 | 504),
+        Error::Io(err) => is_retryable_kind(err.kind()),
+    }
+}
+
+/// Returns true for the kinds of IO error caused by the connection failing or timing out
+fn is_retryable_kind(kind: ErrorKind) -> bool {
+    matches!(
+        kind,
+        ErrorKind::TimedOut
+            | ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::UnexpectedEof
+            | ErrorKind::BrokenPipe
+    )
+}
+
+/// Converts an error from a HTTP client that did not get a response
+///
+/// If it was caused by the connection failing or timing out it becomes that [`io::Error`] so
+/// it is retried, anything else, e.g. a bad url, a failed DNS lookup or TLS handshake,
+/// is an [`Error::Http`] with code 499 that is not.
+#[cfg(any(feature = "http-ureq", feature = "http-reqwest"))]
+pub(crate) fn transport_error(err: &(dyn std::error::Error + 'static)) -> Error {
+    let mut source = Some(err);
+    while let Some(cause) = source {
+        if let Some(io) = cause.downcast_ref::<IoError>() {
+            // a read timeout is reported as `WouldBlock` on some platforms
+            let kind = match io.kind() {
+                ErrorKind::WouldBlock => ErrorKind::TimedOut,
+                kind => kind,
+            };
+            if is_retryable_kind(kind) {
+                return Error::Io(IoError::new(kind, err.to_string()));
+            }
+        }
+        source = cause.source();
+    }
+    Error::Http {
+        code: 499,
+        message: err.to_string(),
+    }
+}
+
+/// Calls `request` until it succeeds, fails with an error that is not worth retrying,
+/// or there are no retries left
+fn with_retries<T>(config: &HttpConfig, mut request: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut attempt = 0;
+    loop {
+        match request() {
+            Err(err) if is_retryable(&err) => match config.retry_delay(attempt) {
+                Some(delay) => sleep(delay),
+                None => return Err(err),
+            },
+            result => return result,
+        }
+        attempt += 1;
+    }
+}
+
+/// Checks an input exists with a `HEAD` request, returning its size if the server sent it
+///
+/// When offline the input has to be in the cache instead.
+pub(crate) fn check_input(url: &str, config: &HttpConfig) -> Result<Option<u64>> {
+    if config.offline {
+        return cache::cached_len(url, config).map(Some);
+    }
+    let resp = with_retries(config, || {
+        backend::backend(url)?
+            .head(&HttpRequest::new("HEAD", url, &[], config)?)?
+            .error_for_status()
+    });
+    let resp = match resp {
+        // not every server supports HEAD, so start a GET instead and drop the body
+        Err(Error::Http {
+            code: 405 | 501, ..
+        }) => with_retries(config, || get(url, None, None, config))?,
+        resp => resp?,
+    };
+    if encoding::is_encoded(&resp) {
+        return Ok(None);
+    }
+    Ok(resp.content_length())
+}
+
+/// Checks an output can be uploaded to with `method` by sending an `OPTIONS` request
+///
+/// Only a clear refusal is an error, as many servers don't answer `OPTIONS` for a file
+/// that does not exist yet, or at all.
+pub(crate) fn check_output(url: &str, method: Method, config: &HttpConfig) -> Result<()> {
+    let resp = with_retries(config, || {
+        send("OPTIONS", url, &[], &mut io::empty(), Some(0), config)
+    });
+    match resp {
+        Ok(resp) => match resp.header("allow") {
+            Some(allow)
+                if !allow
+                    .split(',')
+                    .any(|allowed| allowed.trim().eq_ignore_ascii_case(method.as_str())) =>
+            {
+                Err(Error::Http {
+                    code: 405,
+                    message: format!("the server only allows {allow}"),
+                })
+            }
+            _ => Ok(()),
+        },
+        Err(Error::Http {
+            code: 400 | 404 | 405 | 501,
+            ..
+        }) => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Writes the body of a PUT or POST request
+///
+/// The request is sent on another thread that reads the body from a pipe.
+pub struct HttpWriter {
+    write: PipeBufWriter,
+    rx: Mutex<Receiver<Result<Option<UploadResponse>>>>,
+    /// the response if the request finished before any of the body was read
+    response: Option<UploadResponse>,
+    /// set once the whole body has been written, so an atomic upload can be moved into place
+    finished: Arc<AtomicBool>,
+}
+
+/// A wrapper for the read end of the pipe that sniches on when data is first read
+/// by sending `Ok(None)` down tx.
+///
+/// This is used so that we can block the code making the put request until ethier:
+/// a) the data is tried to be read, or
+/// b) the request fails before trying to send the payload (bad hostname, invalid auth, etc)
+struct SnitchingReader {
+    read: PipeReader,
+    connected: bool,
+    tx: SyncSender<Result<Option<UploadResponse>>>,
+}
+
+impl Read for SnitchingReader {
+    fn read(&mut self, buffer: &mut [u8]) -> IoResult<usize> {
+        if !self.connected {
+            self.tx
+                .send(Ok(None))
+                .map_err(|e| IoError::new(ErrorKind::Other, e))?;
+            self.connected = true;
+        }
+        self.read.read(buffer)
+    }
+}
+
+impl HttpWriter {
+    pub(crate) fn new(upload: Upload, size: Option<u64>) -> Result<Self> {
+        upload.prepare()?;
+        let (read, write) = pipe::pipe_buffered();
+        let finished = Arc::new(AtomicBool::new(false));
+
+        let (done_tx, rx) = sync_channel(0);
+        let mut snitch = SnitchingReader {
+            read,
+            connected: false,
+            tx: done_tx.clone(),
+        };
+
+        spawn({
+            let finished = finished.clone();
+            move || {
+                let mut attempt = 0;
+                let result = loop {
+                    match upload.send(&mut snitch, size) {
+                        // once the body has started being sent it can't be sent again
+                        Err(err) if !snitch.connected && is_retryable(&err) => {
+                            match upload.config().retry_delay(attempt) {
+                                Some(delay) => sleep(delay),
+                                None => break Err(err),
+                            }
+                        }
+                        result => break result,
+                    }
+                    attempt += 1;
+                };
+                let result =
+                    result.and_then(|resp| upload.complete(resp, finished.load(Ordering::SeqCst)));
+                let _ = done_tx.send(result.map(|resp| Some(UploadResponse::new(resp))));
+            }
+        });
+
+        // either Ok(None) if the other thread started reading, or the result of the request
+        let response = rx.recv().map_err(|_| {
+            IoError::new(
+                ErrorKind::Other,
+                "the upload thread stopped without a result",
+            )
+        })??;
+        let rx = Mutex::new(rx);
+        Ok(HttpWriter {
+            write,
+            rx,
+            response,
+            finished,
+        })
+    }
+
+    pub fn finish(self) -> Result<()> {
+        self.finish_with_response().map(drop)
+    }
+
+    /// Waits for the request to finish, returning the response
+    pub fn finish_with_response(self) -> Result<Option<UploadResponse>> {
+        self.finished.store(true, Ordering::SeqCst);
+        drop(self.write);
+        let rx = self.rx.into_inner().unwrap_or_else(|e| e.into_inner());
+        // if the channel is closed the request was finished before any of the body was read
+        match rx.recv() {
+            Ok(result) => Ok(result?.or(self.response)),
+            Err(_) => Ok(self.response),
+        }
+    }
+}
+
+/// The most of the body of an [`UploadResponse`] that is kept
+const MAX_UPLOAD_RESPONSE_BODY: u64 = 64 * 1024;
+
+/// The response to the `PUT` of an [`Output`](crate::Output) to a url
+///
+/// Returned by [`Output::finish_with_response`](crate::Output::finish_with_response).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct UploadResponse {
+    url: String,
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl UploadResponse {
+    fn new(resp: HttpResponse) -> Self {
+        let mut body = Vec::new();
+        // the upload has already worked so a problem reading the response just leaves less of it
+        let _ = resp
+            .body
+            .take(MAX_UPLOAD_RESPONSE_BODY)
+            .read_to_end(&mut body);
+        UploadResponse {
+            url: resp.url,
+            status: resp.status,
+            headers: resp.headers,
+            body,
+        }
+    }
+
+    /// The url the response came from, after following any redirects
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The status code of the response e.g. `201` when a new file was created
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// All the headers of the response
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// The value of the first header called `name`, ignoring case,
+    /// e.g. `x-amz-version-id`
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The `ETag` header of the new version of the file, including the quotes
+    pub fn etag(&self) -> Option<&str> {
+        self.header("etag")
+    }
+
+    /// The `Location` header, e.g. the url of a file the server created
+    pub fn location(&self) -> Option<&str> {
+        self.header("location")
+    }
+
+    /// The start of the body, at most 64KiB of it
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+}
+
+impl Write for HttpWriter {
+    fn write(&mut self, buffer: &[u8]) -> IoResult<usize> {
+        self.write.write(buffer)
+    }
+    fn flush(&mut self) -> IoResult<()> {
+        self.write.flush()
+    }
+}
+
+impl Debug for HttpWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpWriter").finish()
+    }
+}
+
+/// Reads the body of a GET request
+///
+/// If the server supports range requests seeking is done by dropping the
+/// current response and requesting the rest of the body from the new position.
+///
+/// A body with a `Content-Encoding` of `gzip`, `deflate` or `br` is decoded, whichever backend is used.
+/// The bytes of each response are counted, as sent before they are decoded,
+/// so a body that ends before its `Content-Length` is an [`UnexpectedEof`](ErrorKind::UnexpectedEof) error
+/// rather than a silently truncated file.
+pub struct HttpReader {
+    url: String,
+    config: Arc<HttpConfig>,
+    metadata: HttpMetadata,
+    /// sent as `If-Range` when the rest of the body is requested, so a changed file is noticed
+    validator: Option<String>,
+    length: Option<u64>,
+    seekable: bool,
+    position: u64,
+    /// `None` after seeking, until the next read requests the body from the new position
+    body: Mutex<Option<Box<dyn Read + Send>>>,
+}
+
+impl HttpReader {
+    pub fn new(url: &str, config: Arc<HttpConfig>) -> Result<Self> {
+        let resp = with_retries(&config, || get(url, None, None, &config))?;
+        Self::from_response(url, config, resp)
+    }
+
+    /// Reads the body of a response to a `GET` of `url` that has already been sent
+    pub(crate) fn from_response(
+        url: &str,
+        config: Arc<HttpConfig>,
+        resp: HttpResponse,
+    ) -> Result<Self> {
+        let encoded = encoding::is_encoded(&resp);
+        // the length and ranges of an encoded body are of the encoded bytes,
+        // so the length of the file is unknown and it can't be seeked
+        let length = if encoded { None } else { resp.content_length() };
+        let seekable = !encoded
+            && resp
+                .header("accept-ranges")
+                .map_or(false, |x| x.trim().eq_ignore_ascii_case("bytes"));
+        Ok(HttpReader {
+            url: url.to_owned(),
+            config,
+            metadata: HttpMetadata::new(&resp),
+            validator: resp.validator().map(str::to_owned),
+            length,
+            seekable,
+            position: 0,
+            body: Mutex::new(Some(open_body(resp, 0)?)),
+        })
+    }
+
+    pub fn len(&self) -> Option<u64> {
+        self.length
+    }
+
+    pub fn metadata(&self) -> &HttpMetadata {
+        &self.metadata
+    }
+
+    /// Returns true if the server said it accepts range requests
+    pub fn can_seek(&self) -> bool {
+        self.seekable
+    }
+
+    fn body_mut(&mut self) -> &mut Option<Box<dyn Read + Send>> {
+        self.body.get_mut().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Requests the rest of the body starting from the current position
+    fn reopen(&self) -> Result<Box<dyn Read + Send>> {
+        let resp = match with_retries(&self.config, || {
+            get(
+                &self.url,
+                Some(self.position),
+                self.validator.as_deref(),
+                &self.config,
+            )
+        }) {
+            Ok(resp) => resp,
+            // seeking past the end is allowed, there is just nothing to read
+            Err(Error::Http { code: 416, .. }) => return Ok(Box::new(io::empty())),
+            Err(err) => return Err(err),
+        };
+        if resp.status == 206 {
+            return open_body(resp, self.position);
+        }
+        // a different validator means the whole of a new version of the file was sent,
+        // while the same one means the server ignored the range, so skip to the position
+        if self.validator.is_some() && resp.validator() != self.validator.as_deref() {
+            return Err(Error::other(
+                "the file changed on the server while it was being read",
+            ));
+        }
+        let mut body = open_body(resp, 0)?;
+        let skipped = io::copy(&mut (&mut body).take(self.position), &mut io::sink())?;
+        if skipped < self.position {
+            return Err(truncated_error(skipped, self.position));
+        }
+        Ok(body)
+    }
+
+    /// Reads from the current response, requesting the body from the current position if there isn't one
+    fn read_body(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        if self.body_mut().is_none() {
+            let body = self.reopen()?;
+            *self.body_mut() = Some(body);
+        }
+        Ok(self.body_mut().as_mut().unwrap().read(buffer)?)
+    }
+}
+
+impl Read for HttpReader {
+    fn read(&mut self, buffer: &mut [u8]) -> IoResult<usize> {
+        let mut attempt = 0;
+        loop {
+            match self.read_body(buffer) {
+                Ok(len) => {
+                    self.position += len as u64;
+                    return Ok(len);
+                }
+                // the connection broke part way through the body so request the rest of it
+                Err(err) if is_retryable(&err) => match self.config.retry_delay(attempt) {
+                    Some(delay) => {
+                        sleep(delay);
+                        attempt += 1;
+                        *self.body_mut() = None;
+                    }
+                    None => return Err(err.into()),
+                },
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+impl Seek for HttpReader {
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => checked_offset(self.position, offset),
+            SeekFrom::End(offset) => match self.length {
+                Some(length) => checked_offset(length, offset),
+                None => return Err(Error::seek_error().into()),
+            },
+        }
+        .ok_or_else(|| {
+            IoError::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        if position != self.position {
+            if !self.seekable {
+                return Err(Error::seek_error().into());
+            }
+            self.position = position;
+            *self.body_mut() = None;
+        }
+        Ok(position)
+    }
+}
+
+/// The body of a response, starting at `start` bytes into the file, decoded if it has a `Content-Encoding`
+fn open_body(resp: HttpResponse, start: u64) -> Result<Box<dyn Read + Send>> {
+    let expected = resp.content_length().map(|length| start + length);
+    let encoding = resp.header("content-encoding").map(str::to_owned);
+    let body = CheckedBody {
+        body: resp.body,
+        received: start,
+        expected,
+    };
+    encoding::decode(encoding.as_deref(), Box::new(body))
+}
+
+/// The raw bytes of a response, which is an error if it ends before its `Content-Length`
+struct CheckedBody {
+    body: Box<dyn Read + Send>,
+    received: u64,
+    expected: Option<u64>,
+}
+
+impl Read for CheckedBody {
+    fn read(&mut self, buffer: &mut [u8]) -> IoResult<usize> {
+        let len = self.body.read(buffer)?;
+        match self.expected {
+            Some(expected) if len == 0 && self.received < expected && !buffer.is_empty() => {
+                Err(truncated_error(self.received, expected).into())
+            }
+            _ => {
+                self.received += len as u64;
+                Ok(len)
+            }
+        }
+    }
+}
+
+fn truncated_error(received: u64, expected: u64) -> Error {
+    Error::Io(IoError::new(
+        ErrorKind::UnexpectedEof,
+        format!("the connection closed after {received} of {expected} bytes"),
+    ))
+}
+
+fn checked_offset(base: u64, offset: i64) -> Option<u64> {
+    if offset < 0 {
+        base.checked_sub(offset.unsigned_abs())
+    } else {
+        base.checked_add(offset as u64)
+    }
+}
+
+impl Debug for HttpReader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpReader").finish()
+    }
+}
+
+// the tests send real requests so need one of the built in backends
+#[cfg(all(
+    test,
+    any(feature = "http-ureq", feature = "http-curl", feature = "http-reqwest")
+))]
+mod tests {
+    use super::backend::{default_backend, replace_backend};
+    use super::{content_disposition_filename, is_offline_value, test_server};
+    use crate::{ClioPath, HttpConfig, Input, InputPath, Method, Output, OutputPath, Result};
+    use crate::{HttpBackend, HttpRequest, HttpResponse};
+    use std::io::{Cursor, ErrorKind, Read, Seek, SeekFrom, Write};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tiny_http::Response;
+
+    fn with_config(url: &str, config: HttpConfig) -> Result<ClioPath> {
+        let mut path = ClioPath::new(url)?;
+        path.http_config = Some(Arc::new(config));
+        Ok(path)
+    }
+
+    fn retries(retries: u32) -> HttpConfig {
+        HttpConfig::default()
+            .retries(retries)
+            .retry_backoff(Duration::from_millis(1))
+    }
+
+    #[test]
+    fn test_seek_with_ranges() -> Result<()> {
+        let data: Vec<u8> = (0..=255).collect();
+        let url = test_server::serve_bytes(data.clone(), true);
+        let mut input = Input::new(url.as_str())?;
+        assert!(input.can_seek());
+        assert_eq!(input.len(), Some(256));
+
+        let mut buf = [0; 4];
+        input.seek(SeekFrom::Start(100))?;
+        input.read_exact(&mut buf)?;
+        assert_eq!(buf, [100, 101, 102, 103]);
+
+        input.seek(SeekFrom::End(-2))?;
+        let mut rest = Vec::new();
+        input.read_to_end(&mut rest)?;
+        assert_eq!(rest, [254, 255]);
+
+        input.seek(SeekFrom::Current(-10))?;
+        input.read_exact(&mut buf)?;
+        assert_eq!(buf, [246, 247, 248, 249]);
+
+        input.seek(SeekFrom::Start(1000))?;
+        assert_eq!(input.read(&mut buf)?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_seek_without_ranges() -> Result<()> {
+        let url = test_server::serve_bytes(b"hello world".to_vec(), false);
+        let mut input = Input::new(url.as_str())?;
+        assert!(!input.can_seek());
+        assert_eq!(input.stream_position()?, 0);
+        assert!(input.seek(SeekFrom::Start(6)).is_err());
+        let mut contents = String::new();
+        input.read_to_string(&mut contents)?;
+        assert_eq!(contents, "hello world");
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_headers() -> Result<()> {
+        let url = test_server::serve(|request| {
+            let body = format!(
+                "{}|{}",
+                test_server::request_header(&request, "authorization").unwrap_or_default(),
+                test_server::request_header(&request, "user-agent").unwrap_or_default()
+            );
+            let _ = request.respond(Response::from_string(body));
+        });
+        let path = with_config(
+            &url,
+            HttpConfig::default()
+                .bearer_auth("token")
+                .user_agent("clio-test"),
+        )?;
+        let mut contents = String::new();
+        Input::new(path)?.read_to_string(&mut contents)?;
+        assert_eq!(contents, "Bearer token|clio-test");
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_retries() -> Result<()> {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let url = test_server::serve({
+            let requests = requests.clone();
+            move |request| {
+                let response = match requests.fetch_add(1, Ordering::SeqCst) {
+                    0 => Response::from_string("busy").with_status_code(503),
+                    _ => Response::from_string("hello"),
+                };
+                let _ = request.respond(response);
+            }
+        });
+        let mut contents = String::new();
+        Input::new(with_config(&url, retries(1))?)?.read_to_string(&mut contents)?;
+        assert_eq!(contents, "hello");
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        requests.store(0, Ordering::SeqCst);
+        let err = Input::new(with_config(&url, retries(0))?).unwrap_err();
+        assert!(matches!(err, crate::Error::Http { code: 503, .. }));
+        Ok(())
+    }
+
+    #[test]
+    fn test_put_retries_replayable_body() -> Result<()> {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let url = test_server::serve({
+            let received = received.clone();
+            move |mut request| {
+                let mut body = String::new();
+                request.as_reader().read_to_string(&mut body).unwrap();
+                let mut received = received.lock().unwrap();
+                received.push(body);
+                let status = if received.len() == 1 { 503 } else { 201 };
+                let _ = request.respond(Response::empty(status));
+            }
+        });
+        let mut input = Cursor::new(b"skip:payload".to_vec());
+        input.seek(SeekFrom::Start(5))?;
+        let output = OutputPath::new(with_config(&url, retries(2))?)?;
+        assert_eq!(output.copy_from(&mut input)?, 7);
+        assert_eq!(*received.lock().unwrap(), ["payload", "payload"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_streaming_put() -> Result<()> {
+        let received = Arc::new(Mutex::new(String::new()));
+        let url = test_server::serve({
+            let received = received.clone();
+            move |mut request| {
+                let mut received = received.lock().unwrap();
+                request.as_reader().read_to_string(&mut received).unwrap();
+                let _ = request.respond(Response::empty(204));
+            }
+        });
+        let mut output = Output::new(url.as_str())?;
+        write!(output, "hello ")?;
+        write!(output, "world")?;
+        output.finish()?;
+        assert_eq!(*received.lock().unwrap(), "hello world");
+        Ok(())
+    }
+
+    #[test]
+    fn test_content_disposition() {
+        let filename = |header| content_disposition_filename(header);
+        assert_eq!(
+            filename("attachment; filename=\"report.csv\""),
+            Some("report.csv".to_owned())
+        );
+        assert_eq!(
+            filename("attachment; filename=plain.txt; size=3"),
+            Some("plain.txt".to_owned())
+        );
+        assert_eq!(
+            filename("attachment; filename=\"a.txt\"; filename*=UTF-8''na%C3%AFve%20file.txt"),
+            Some("naïve file.txt".to_owned())
+        );
+        assert_eq!(
+            filename("attachment; filename=\"../../etc/passwd\""),
+            Some("passwd".to_owned())
+        );
+        assert_eq!(filename("attachment; filename=\"..\""), None);
+        assert_eq!(filename("inline"), None);
+    }
+
+    #[test]
+    fn test_metadata_after_redirect() -> Result<()> {
+        let url = test_server::serve(|request| {
+            let response = if request.url() == "/start" {
+                Response::from_string("")
+                    .with_status_code(302)
+                    .with_header(test_server::header("Location", "/file"))
+            } else {
+                Response::from_string("a,b")
+                    .with_header(test_server::header("Content-Type", "text/csv"))
+                    .with_header(test_server::header("ETag", "\"v1\""))
+                    .with_header(test_server::header(
+                        "Last-Modified",
+                        "Wed, 21 Oct 2015 07:28:00 GMT",
+                    ))
+                    .with_header(test_server::header(
+                        "Content-Disposition",
+                        "attachment; filename=\"data.csv\"",
+                    ))
+            };
+            let _ = request.respond(response);
+        });
+        let input = Input::new(format!("{url}start").as_str())?;
+        let metadata = input.http_metadata().unwrap();
+        assert_eq!(metadata.url(), format!("{url}file"));
+        assert_eq!(metadata.status(), 200);
+        assert_eq!(metadata.content_type(), Some("text/csv"));
+        assert_eq!(metadata.etag(), Some("\"v1\""));
+        assert_eq!(
+            metadata.last_modified(),
+            Some("Wed, 21 Oct 2015 07:28:00 GMT")
+        );
+        assert_eq!(metadata.filename(), Some("data.csv"));
+        assert!(Input::std().http_metadata().is_none());
+        Ok(())
+    }
+
+    /// Sends the first half of the body then closes the connection,
+    /// then sends the rest when asked for it with a range request
+    fn serve_truncated() -> String {
+        test_server::serve_raw(|mut stream, head| {
+            let response = if head.to_ascii_lowercase().contains("range: bytes=5-") {
+                "HTTP/1.1 206 Partial Content\r\nContent-Length: 5\r\nContent-Range: bytes 5-9/10\r\n\r\nworld"
+            } else {
+                "HTTP/1.1 200 OK\r\nContent-Length: 10\r\nAccept-Ranges: bytes\r\n\r\nhello"
+            };
+            let _ = stream.write_all(response.as_bytes());
+        })
+    }
+
+    #[test]
+    fn test_truncated_body() -> Result<()> {
+        let url = serve_truncated();
+        let mut input = Input::new(with_config(&url, retries(0))?)?;
+        let mut contents = Vec::new();
+        let err = input.read_to_end(&mut contents).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(contents, b"hello");
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncated_body_resumed() -> Result<()> {
+        let url = serve_truncated();
+        let mut input = Input::new(with_config(&url, retries(1))?)?;
+        let mut contents = String::new();
+        input.read_to_string(&mut contents)?;
+        assert_eq!(contents, "helloworld");
+        Ok(())
+    }
+
+    #[test]
+    fn test_changed_body_not_resumed() -> Result<()> {
+        let heads = Arc::new(Mutex::new(Vec::new()));
+        let seen = heads.clone();
+        // the file changes after the first half was sent, so the server ignores the range
+        let url = test_server::serve_raw(move |mut stream, head| {
+            let response = if head.to_ascii_lowercase().contains("range: bytes=5-") {
+                "HTTP/1.1 200 OK\r\nContent-Length: 10\r\nETag: \"v2\"\r\n\r\nHELLOWORLD"
+            } else {
+                "HTTP/1.1 200 OK\r\nContent-Length: 10\r\nETag: \"v1\"\r\nAccept-Ranges: bytes\r\n\r\nhello"
+            };
+            seen.lock().unwrap().push(head.to_ascii_lowercase());
+            let _ = stream.write_all(response.as_bytes());
+        });
+        let mut input = Input::new(with_config(&url, retries(1))?)?;
+        let mut contents = Vec::new();
+        let err = input.read_to_end(&mut contents).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Other);
+        assert_eq!(contents, b"hello");
+        assert!(heads.lock().unwrap()[1].contains("if-range: \"v1\""));
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_body_not_retried() -> Result<()> {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let count = requests.clone();
+        let url = test_server::serve_raw(move |mut stream, _| {
+            count.fetch_add(1, Ordering::SeqCst);
+            let _ = stream.write_all(
+                b"HTTP/1.1 200 OK\r\nContent-Length: 21\r\nContent-Encoding: gzip\r\n\r\nthis is not gzip data",
+            );
+        });
+        let mut input = Input::new(with_config(&url, retries(3))?)?;
+        let mut contents = Vec::new();
+        assert!(input.read_to_end(&mut contents).is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_late_transfer_error() -> Result<()> {
+        // the body is cut short without the connection closing cleanly
+        let url = test_server::serve_raw(|mut stream, _| {
+            let _ = stream.write_all(
+                b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\nzz",
+            );
+        });
+        let mut input = Input::new(with_config(&url, retries(0))?)?;
+        let mut contents = Vec::new();
+        assert!(input.read_to_end(&mut contents).is_err());
+        Ok(())
+    }
+
+    fn builtin_backends() -> Vec<Box<dyn HttpBackend>> {
+        vec![
+            #[cfg(feature = "http-ureq")]
+            Box::new(crate::UreqBackend),
+            #[cfg(feature = "http-curl")]
+            Box::new(crate::CurlBackend),
+            #[cfg(feature = "http-reqwest")]
+            Box::new(crate::ReqwestBackend::new()),
+        ]
+    }
+
+    #[test]
+    fn test_builtin_backends() -> Result<()> {
+        let url = test_server::serve(|mut request| {
+            let mut body = String::new();
+            request.as_reader().read_to_string(&mut body).unwrap();
+            let range = test_server::request_header(&request, "range").unwrap_or_default();
+            let reply = format!("{} {} {}", request.method(), range, body);
+            let _ = request.respond(
+                Response::from_string(reply).with_header(test_server::header("X-Test", "yes")),
+            );
+        });
+        let config = HttpConfig::default();
+        let range = [("Range".to_owned(), "bytes=2-".to_owned())];
+        for backend in builtin_backends() {
+            let resp = backend.get(&HttpRequest::new("GET", &url, &range, &config)?)?;
+            assert_eq!(resp.status(), 200);
+            assert_eq!(resp.header("x-test"), Some("yes"));
+            let mut body = String::new();
+            resp.into_body().read_to_string(&mut body)?;
+            assert_eq!(body, "GET bytes=2- ");
+
+            let resp = backend.head(&HttpRequest::new("HEAD", &url, &[], &config)?)?;
+            assert_eq!(resp.header("x-test"), Some("yes"));
+            let mut body = Vec::new();
+            resp.into_body().read_to_end(&mut body)?;
+            assert!(body.is_empty());
+
+            let request = HttpRequest::new("PUT", &url, &[], &config)?;
+            let resp = backend.send(&request, &mut &b"data"[..], Some(4))?;
+            let mut body = String::new();
+            resp.into_body().read_to_string(&mut body)?;
+            assert_eq!(body, "PUT  data");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_retry_only_connection_errors() -> Result<()> {
+        let closed = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+            format!("http://{}/", listener.local_addr()?)
+        };
+        let config = HttpConfig::default();
+        for backend in builtin_backends() {
+            let refused = backend
+                .get(&HttpRequest::new("GET", &closed, &[], &config)?)
+                .unwrap_err();
+            assert!(super::is_retryable(&refused), "{:?}", refused);
+
+            let request = HttpRequest::new("GET", "http://nonexistent.invalid/", &[], &config)?;
+            let unresolved = backend.get(&request).unwrap_err();
+            assert!(!super::is_retryable(&unresolved), "{:?}", unresolved);
+        }
+        Ok(())
+    }
+
+    /// Answers requests to `http://clio.test/` itself and sends the rest to the built in backend,
+    /// so registering it does not break the other tests
+    struct TestBackend {
+        uploaded: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl TestBackend {
+        fn answer(&self, request: &HttpRequest<'_>, body: &[u8]) -> HttpResponse {
+            HttpResponse::new(
+                request.url(),
+                200,
+                vec![("Content-Length".to_owned(), body.len().to_string())],
+                Box::new(Cursor::new(body.to_vec())),
+            )
+        }
+    }
+
+    impl HttpBackend for TestBackend {
+        fn get(&self, request: &HttpRequest<'_>) -> Result<HttpResponse> {
+            match request.url() {
+                "http://clio.test/hello" => {
+                    Ok(self.answer(request, b"hello from the test backend"))
+                }
+                "http://clio.test/missing" => Ok(HttpResponse::new(
+                    request.url(),
+                    404,
+                    vec![],
+                    Box::new(std::io::empty()),
+                )),
+                _ => default_backend().unwrap().get(request),
+            }
+        }
+
+        fn send(
+            &self,
+            request: &HttpRequest<'_>,
+            body: &mut dyn Read,
+            size: Option<u64>,
+        ) -> Result<HttpResponse> {
+            if request.url() == "http://clio.test/panic" {
+                panic!("the test backend failed");
+            } else if request.url().starts_with("http://clio.test/") {
+                body.read_to_end(&mut self.uploaded.lock().unwrap())?;
+                Ok(self.answer(request, b""))
+            } else {
+                default_backend().unwrap().send(request, body, size)
+            }
+        }
+
+        fn head(&self, request: &HttpRequest<'_>) -> Result<HttpResponse> {
+            default_backend().unwrap().head(request)
+        }
+    }
+
+    /// Puts back the backend that was set before a test replaced it
+    struct RestoreBackend(Option<Arc<dyn HttpBackend>>);
+
+    impl Drop for RestoreBackend {
+        fn drop(&mut self) {
+            replace_backend(self.0.take());
+        }
+    }
+
+    #[test]
+    fn test_custom_backend() -> Result<()> {
+        let uploaded = Arc::new(Mutex::new(Vec::new()));
+        let _restore = RestoreBackend(replace_backend(None));
+        crate::set_http_backend(TestBackend {
+            uploaded: uploaded.clone(),
+        });
+
+        let mut input = Input::new("http://clio.test/hello")?;
+        assert_eq!(input.len(), Some(27));
+        let mut contents = String::new();
+        input.read_to_string(&mut contents)?;
+        assert_eq!(contents, "hello from the test backend");
+
+        let err = Input::new("http://clio.test/missing").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+
+        let mut output = Output::new("http://clio.test/upload")?;
+        output.write_all(b"uploaded")?;
+        output.finish()?;
+        assert_eq!(*uploaded.lock().unwrap(), b"uploaded");
+
+        let err = Output::new("http://clio.test/panic").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Other);
+        Ok(())
+    }
+
+    fn checked(url: &str) -> Result<ClioPath> {
+        let mut path = ClioPath::new(url)?;
+        path.check_remote = true;
+        Ok(path)
+    }
+
+    #[test]
+    fn test_check_remote_input() -> Result<()> {
+        let url = test_server::serve(|request| {
+            let response = match request.url() {
+                "/file" => Response::from_string("hello"),
+                "/secret" => Response::from_string("").with_status_code(403),
+                "/no-head" if request.method() == &tiny_http::Method::Head => {
+                    Response::from_string("").with_status_code(405)
+                }
+                "/no-head" => Response::from_string("hi"),
+                _ => Response::from_string("").with_status_code(404),
+            };
+            let _ = request.respond(response);
+        });
+        let path = InputPath::new(checked(&format!("{url}file"))?)?;
+        assert_eq!(path.len(), Some(5));
+        let path = InputPath::new(checked(&format!("{url}no-head"))?)?;
+        assert_eq!(path.len(), Some(2));
+
+        let err = InputPath::new(checked(&format!("{url}missing"))?).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        let err = InputPath::new(checked(&format!("{url}secret"))?).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+
+        // without the check nothing is sent until it is opened
+        let path = InputPath::new(format!("{url}missing").as_str())?;
+        assert_eq!(path.len(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_remote_output() -> Result<()> {
+        let url = test_server::serve(|request| {
+            let response = match request.url() {
+                "/writable" => {
+                    Response::from_string("").with_header(test_server::header("Allow", "GET, PUT"))
+                }
+                "/read-only" => Response::from_string("")
+                    .with_header(test_server::header("Allow", "GET, HEAD, OPTIONS")),
+                "/secret" => Response::from_string("").with_status_code(401),
+                _ => Response::from_string("").with_status_code(405),
+            };
+            let _ = request.respond(response);
+        });
+        OutputPath::new(checked(&format!("{url}writable"))?)?;
+        OutputPath::new(checked(&format!("{url}unknown"))?)?;
+        let err = OutputPath::new(checked(&format!("{url}read-only"))?).unwrap_err();
+        assert!(matches!(err, crate::Error::Http { code: 405, .. }));
+        let mut post = checked(&format!("{url}writable"))?;
+        post.http_method = Method::Post;
+        let err = OutputPath::new(post).unwrap_err();
+        assert!(matches!(err, crate::Error::Http { code: 405, .. }));
+        let err = OutputPath::new(checked(&format!("{url}secret"))?).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        Ok(())
+    }
+
+    /// Serves a single file that can be replaced with a `PUT`, honouring `If-Match` and `If-None-Match`
+    fn serve_versioned() -> String {
+        // the contents and version of the file, `None` until it has been put
+        let file: Arc<Mutex<Option<(String, u32)>>> = Arc::new(Mutex::new(None));
+        test_server::serve(move |mut request| {
+            let (if_match, if_none_match) = (
+                test_server::request_header(&request, "if-match"),
+                test_server::request_header(&request, "if-none-match"),
+            );
+            let mut file = file.lock().unwrap();
+            let etag = file.as_ref().map(|(_, version)| format!("\"v{version}\""));
+            let response = if request.method() == &tiny_http::Method::Get {
+                match &*file {
+                    Some((contents, _)) => Response::from_string(contents.clone())
+                        .with_header(test_server::header("ETag", etag.as_deref().unwrap())),
+                    None => Response::from_string("").with_status_code(404),
+                }
+            } else if if_match.map_or(false, |tag| Some(tag) != etag)
+                || (if_none_match.is_some() && etag.is_some())
+            {
+                Response::from_string("").with_status_code(412)
+            } else {
+                let mut contents = String::new();
+                request.as_reader().read_to_string(&mut contents).unwrap();
+                let version = file.as_ref().map_or(1, |(_, version)| version + 1);
+                *file = Some((contents, version));
+                Response::from_string("").with_status_code(204)
+            };
+            let _ = request.respond(response);
+        })
+    }
+
+    fn put(output: Result<Output>, contents: &str) -> Result<()> {
+        let mut output = output?;
+        output.write_all(contents.as_bytes())?;
+        output.finish()
+    }
+
+    #[test]
+    fn test_conditional_put() -> Result<()> {
+        let url = serve_versioned();
+        put(
+            OutputPath::new(url.as_str())?.create_new_with_len(5),
+            "first",
+        )?;
+        let err = put(OutputPath::new(url.as_str())?.create_new(), "clobbered").unwrap_err();
+        assert!(matches!(err, crate::Error::Http { code: 412, .. }));
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+        let mut input = Input::new(url.as_str())?;
+        let etag = input.http_metadata().unwrap().etag().unwrap().to_owned();
+        let mut contents = String::new();
+        input.read_to_string(&mut contents)?;
+        assert_eq!(contents, "first");
+
+        put(
+            OutputPath::new(url.as_str())?.create_if_match_with_len(&etag, 6),
+            "second",
+        )?;
+        // the etag is now out of date
+        let err = put(
+            OutputPath::new(url.as_str())?.create_if_match(&etag),
+            "third",
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+        let mut contents = String::new();
+        Input::new(url.as_str())?.read_to_string(&mut contents)?;
+        assert_eq!(contents, "second");
+
+        let err = OutputPath::new("-")?.create_new().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+        Ok(())
+    }
+
+    #[test]
+    fn test_finish_with_response() -> Result<()> {
+        let url = test_server::serve(|mut request| {
+            let mut body = String::new();
+            request.as_reader().read_to_string(&mut body).unwrap();
+            let response = Response::from_string(format!("stored {} bytes", body.len()))
+                .with_status_code(201)
+                .with_header(test_server::header("ETag", "\"abc\""))
+                .with_header(test_server::header("Location", "/files/1"))
+                .with_header(test_server::header("X-Version-Id", "7"));
+            let _ = request.respond(response);
+        });
+        let mut output = Output::new(url.as_str())?;
+        output.write_all(b"hello")?;
+        let response = output.finish_with_response()?.unwrap();
+        assert_eq!(response.status(), 201);
+        assert_eq!(response.etag(), Some("\"abc\""));
+        assert_eq!(response.location(), Some("/files/1"));
+        assert_eq!(response.header("x-version-id"), Some("7"));
+        assert_eq!(response.body(), b"stored 5 bytes");
+
+        let tmp = tempfile::tempdir()?;
+        let output = Output::new(&tmp.path().join("local"))?;
+        assert!(output.finish_with_response()?.is_none());
+        Ok(())
+    }
+
+    /// Replies to each request with its method, content type and body
+    fn serve_echo() -> String {
+        test_server::serve(|mut request| {
+            let content_type =
+                test_server::request_header(&request, "content-type").unwrap_or_default();
+            let mut body = String::new();
+            request.as_reader().read_to_string(&mut body).unwrap();
+            let reply = format!("{}\n{}\n{}", request.method(), content_type, body);
+            let _ = request.respond(Response::from_string(reply));
+        })
+    }
+
+    fn echoed(output: Output, contents: &[u8]) -> Result<Vec<String>> {
+        let mut output = output;
+        output.write_all(contents)?;
+        let response = output.finish_with_response()?.unwrap();
+        let body = String::from_utf8_lossy(response.body()).into_owned();
+        Ok(body.splitn(3, '\n').map(str::to_owned).collect())
+    }
+
+    #[test]
+    fn test_post() -> Result<()> {
+        let url = serve_echo();
+        let mut path = ClioPath::new(format!("{url}upload").as_str())?;
+        path.http_method = Method::Post;
+        assert_eq!(
+            echoed(Output::new(path.clone())?, b"data")?,
+            ["POST", "", "data"]
+        );
+        path.http_method = Method::Put;
+        assert_eq!(echoed(Output::new(path)?, b"data")?, ["PUT", "", "data"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_multipart() -> Result<()> {
+        let url = serve_echo();
+        let mut path = ClioPath::new(format!("{url}files/my%20report.csv").as_str())?;
+        path.http_method = Method::Post;
+        path.multipart = Some("upload".to_owned());
+
+        let reply = echoed(Output::new(path.clone())?, b"a,b\n1,2")?;
+        assert_eq!(reply[0], "POST");
+        let boundary = reply[1]
+            .strip_prefix("multipart/form-data; boundary=")
+            .unwrap();
+        assert_eq!(
+            reply[2],
+            format!(
+                "--{boundary}\r\n\
+                Content-Disposition: form-data; name=\"upload\"; filename=\"my report.csv\"\r\n\
+                Content-Type: application/octet-stream\r\n\r\n\
+                a,b\n1,2\r\n--{boundary}--\r\n"
+            )
+        );
+
+        // with a known length the Content-Length has to include the rest of the form
+        let received = Arc::new(Mutex::new(String::new()));
+        let url = test_server::serve({
+            let received = received.clone();
+            move |mut request| {
+                let mut received = received.lock().unwrap();
+                request.as_reader().read_to_string(&mut received).unwrap();
+                let _ = request.respond(Response::empty(201));
+            }
+        });
+        path.path = ClioPath::new(format!("{url}report.csv").as_str())?.path;
+        let mut input = Cursor::new(b"a,b\n1,2".to_vec());
+        OutputPath::new(path)?.copy_from(&mut input)?;
+        let received = received.lock().unwrap();
+        assert!(received.contains("filename=\"report.csv\"\r\n"));
+        assert!(received.contains("\r\n\r\na,b\n1,2\r\n--"));
+        assert!(received.ends_with("--\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache() -> Result<()> {
+        // the body and etag to serve, and the conditional header of each request
+        let state = Arc::new(Mutex::new(("first".to_owned(), Some("\"v1\""))));
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let url = test_server::serve({
+            let (state, seen) = (state.clone(), seen.clone());
+            move |request| {
+                let condition = test_server::request_header(&request, "if-none-match")
+                    .or_else(|| test_server::request_header(&request, "if-modified-since"));
+                seen.lock().unwrap().push(condition.clone());
+                let (body, etag) = state.lock().unwrap().clone();
+                let last_modified = "Wed, 21 Oct 2015 07:28:00 GMT";
+                let response = if condition.as_deref() == Some(etag.unwrap_or(last_modified)) {
+                    Response::from_string("").with_status_code(304)
+                } else {
+                    Response::from_string(body)
+                };
+                let mut response =
+                    response.with_header(test_server::header("Last-Modified", last_modified));
+                if let Some(etag) = etag {
+                    response = response.with_header(test_server::header("ETag", etag));
+                }
+                let _ = request.respond(response);
+            }
+        });
+        let tmp = tempfile::tempdir()?;
+        let online = HttpConfig::default().cache_dir(tmp.path());
+        let offline = online.clone().offline(true);
+        let read = |config: &HttpConfig| -> Result<String> {
+            let mut input = Input::new(with_config(&url, config.clone())?)?;
+            let mut contents = String::new();
+            input.read_to_string(&mut contents)?;
+            Ok(contents)
+        };
+
+        assert_eq!(read(&online)?, "first");
+        // the server still says it is v1 so the cached copy is used
+        state.lock().unwrap().0 = "changed without a new etag".to_owned();
+        let input = Input::new(with_config(&url, online.clone())?)?;
+        assert!(input.can_seek());
+        assert_eq!(input.len(), Some(5));
+        assert_eq!(input.http_metadata().unwrap().etag(), Some("\"v1\""));
+        assert_eq!(read(&online)?, "first");
+
+        *state.lock().unwrap() = ("second".to_owned(), None);
+        assert_eq!(read(&online)?, "second");
+        // the head and the body that replaced the first one
+        assert_eq!(std::fs::read_dir(tmp.path())?.count(), 2);
+        // without an etag it is revalidated by date
+        assert_eq!(read(&online)?, "second");
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [
+                None,
+                Some("\"v1\"".to_owned()),
+                Some("\"v1\"".to_owned()),
+                Some("\"v1\"".to_owned()),
+                Some("Wed, 21 Oct 2015 07:28:00 GMT".to_owned()),
+            ]
+        );
+
+        // offline the server is never asked
+        state.lock().unwrap().0 = "third".to_owned();
+        assert_eq!(read(&offline)?, "second");
+        assert_eq!(seen.lock().unwrap().len(), 5);
+        assert_eq!(
+            InputPath::new(with_config(&url, offline.clone())?)?.len(),
+            Some(6)
+        );
+        let missing = with_config(&format!("{url}missing"), offline)?;
+        assert_eq!(
+            Input::new(missing.clone()).unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_key() -> Result<()> {
+        // echoes the request headers the response depends on, which never change for a path
+        let url = test_server::serve(|request| {
+            let body = format!(
+                "{} {}",
+                test_server::request_header(&request, "authorization").unwrap_or_default(),
+                test_server::request_header(&request, "accept-language").unwrap_or_default()
+            );
+            let response = if test_server::request_header(&request, "if-none-match").is_some() {
+                Response::from_string("").with_status_code(304)
+            } else {
+                Response::from_string(body)
+            };
+            let response = response
+                .with_header(test_server::header("ETag", "\"v1\""))
+                .with_header(test_server::header("Vary", "Accept-Language"));
+            let response = match request.url() {
+                "/secret" => response
+                    .with_header(test_server::header("Cache-Control", "private, max-age=60")),
+                _ => response,
+            };
+            let _ = request.respond(response);
+        });
+        let tmp = tempfile::tempdir()?;
+        let read = |path: &str, config: &HttpConfig| -> Result<String> {
+            let mut input = Input::new(with_config(&format!("{url}{path}"), config.clone())?)?;
+            let mut contents = String::new();
+            input.read_to_string(&mut contents)?;
+            Ok(contents)
+        };
+        let cached =
+            |path: &str, config: &HttpConfig| read(path, &config.clone().offline(true)).ok();
+        let config = HttpConfig::default().cache_dir(tmp.path());
+        let alice = config.clone().bearer_auth("alice");
+        let bob = config.clone().bearer_auth("bob");
+
+        assert_eq!(read("file", &alice)?, "Bearer alice ");
+        assert_eq!(cached("file", &alice).as_deref(), Some("Bearer alice "));
+        assert_eq!(cached("file", &bob), None);
+        assert_eq!(read("file", &bob)?, "Bearer bob ");
+        assert_eq!(read("file", &alice)?, "Bearer alice ");
+
+        let french = alice.clone().header("Accept-Language", "fr");
+        assert_eq!(cached("file", &french), None);
+        assert_eq!(read("file", &french)?, "Bearer alice fr");
+        assert_eq!(cached("file", &alice), None);
+
+        assert_eq!(read("secret", &alice)?, "Bearer alice ");
+        assert_eq!(cached("secret", &alice), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_download() -> Result<()> {
+        // the first response to each version of the file is cut short,
+        // a range request with the right If-Range gets the rest of it
+        let version = Arc::new(Mutex::new(1));
+        let heads = Arc::new(Mutex::new(Vec::new()));
+        let url = test_server::serve_raw({
+            let (version, heads) = (version.clone(), heads.clone());
+            move |mut stream, head| {
+                let head = head.to_ascii_lowercase();
+                let version = *version.lock().unwrap();
+                let body = if version == 1 {
+                    "helloworld"
+                } else {
+                    "HELLOWORLD"
+                };
+                let etag = format!("\"v{version}\"");
+                let response = if head.contains("range: bytes=5-")
+                    && head.contains(&format!("if-range: {etag}"))
+                {
+                    format!(
+                        "HTTP/1.1 206 Partial Content\r\nContent-Length: 5\r\n\
+                        Content-Range: bytes 5-9/10\r\nETag: {etag}\r\n\r\n{}",
+                        &body[5..]
+                    )
+                } else {
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: 10\r\nETag: {etag}\r\n\r\n{}",
+                        &body[..5]
+                    )
+                };
+                heads.lock().unwrap().push(head);
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+        let tmp = tempfile::tempdir()?;
+        let dest_path = tmp.path().join("file.txt");
+        let dest = OutputPath::new(&dest_path)?;
+        let source = with_config(&url, retries(0))?;
+
+        let err = crate::download(&source, &dest).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        assert!(!dest_path.exists());
+        // the partial file is named after the hex encoded etag
+        let partial = tmp.path().join("file.txt.22763122.part");
+        assert_eq!(std::fs::read(&partial)?, b"hello");
+
+        // a second attempt picks up where the first stopped
+        assert_eq!(crate::download(&source, &dest)?, 10);
+        assert_eq!(std::fs::read(&dest_path)?, b"helloworld");
+        assert!(heads.lock().unwrap()[1].contains("if-range: \"v1\""));
+        assert_eq!(std::fs::read_dir(tmp.path())?.count(), 1);
+
+        // if the file changes the partial download is thrown away
+        let err = crate::download(&source, &dest).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        *version.lock().unwrap() = 2;
+        crate::download(&with_config(&url, retries(1))?, &dest)?;
+        assert_eq!(std::fs::read(&dest_path)?, b"HELLOWORLD");
+        assert!(!partial.exists());
+        assert_eq!(std::fs::read_dir(tmp.path())?.count(), 1);
+
+        let err = crate::download(&ClioPath::new("-")?, &dest).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+        Ok(())
+    }
+
+    #[test]
+    fn test_offline_value() {
+        assert!(!is_offline_value(None));
+        assert!(!is_offline_value(Some("".into())));
+        assert!(!is_offline_value(Some("0".into())));
+        assert!(is_offline_value(Some("1".into())));
+        assert!(is_offline_value(Some("true".into())));
+    }
+
+    /// Serves the body of `/gzip`, `/deflate` and `/br` with that `Content-Encoding`
+    /// if the request accepts it
+    fn serve_encoded() -> String {
+        use flate2::write::{GzEncoder, ZlibEncoder};
+        use flate2::Compression;
+        test_server::serve(|request| {
+            let accepted =
+                test_server::request_header(&request, "accept-encoding").unwrap_or_default();
+            let coding = request.url().trim_start_matches('/').to_owned();
+            let body = match coding.as_str() {
+                "gzip" => {
+                    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                    encoder.write_all(b"hello gzip").unwrap();
+                    encoder.finish().unwrap()
+                }
+                "deflate" => {
+                    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                    encoder.write_all(b"hello deflate").unwrap();
+                    encoder.finish().unwrap()
+                }
+                // the brotli encoding of "X"
+                _ => vec![0x0b, 0x00, 0x80, 0x58, 0x03],
+            };
+            let response = if accepted.contains(coding.as_str()) {
+                Response::from_data(body)
+                    .with_header(test_server::header("Content-Encoding", &coding))
+            } else {
+                Response::from_string(format!("not encoded, accepted {accepted:?}"))
+            };
+            let response = response.with_header(test_server::header("Accept-Ranges", "bytes"));
+            let _ = request.respond(response);
+        })
+    }
+
+    #[test]
+    fn test_content_encoding() -> Result<()> {
+        let url = serve_encoded();
+        let config = Arc::new(HttpConfig::default());
+        let expected = [
+            ("gzip", "hello gzip"),
+            ("deflate", "hello deflate"),
+            ("br", "X"),
+        ];
+        for backend in builtin_backends() {
+            for (coding, contents) in expected {
+                let url = format!("{url}{coding}");
+                let headers: Vec<_> = super::encoding::accept_encoding(&config, false)
+                    .into_iter()
+                    .collect();
+                let resp = backend.get(&HttpRequest::new("GET", &url, &headers, &config)?)?;
+                let mut reader = super::HttpReader::from_response(&url, config.clone(), resp)?;
+                assert_eq!(reader.len(), None);
+                assert!(!reader.can_seek());
+                let mut body = String::new();
+                reader.read_to_string(&mut body)?;
+                assert_eq!(body, contents);
+            }
+        }
+
+        let mut input = Input::new(format!("{url}gzip").as_str())?;
+        assert_eq!(input.len(), None);
+        let mut body = String::new();
+        input.read_to_string(&mut body)?;
+        assert_eq!(body, "hello gzip");
+
+        // an Accept-Encoding set in the config is sent instead
+        let path = with_config(
+            &format!("{url}gzip"),
+            HttpConfig::default().header("Accept-Encoding", "identity"),
+        )?;
+        let mut body = String::new();
+        Input::new(path)?.read_to_string(&mut body)?;
+        assert_eq!(body, "not encoded, accepted \"identity\"");
+        Ok(())
+    }
+
+    #[test]
+    fn test_encoded_body_resumed() -> Result<()> {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        let contents: String = (0..2000).map(|i| format!("line {i}\n")).collect();
+        let url = test_server::serve_raw({
+            let contents = contents.clone();
+            move |mut stream, head| {
+                let head = head.to_ascii_lowercase();
+                let start = head
+                    .split("range: bytes=")
+                    .nth(1)
+                    .and_then(|range| range.split('-').next())
+                    .and_then(|start| start.parse::<usize>().ok());
+                let response = match start {
+                    // the rest is requested without compression
+                    Some(start) => {
+                        assert!(head.contains("accept-encoding: identity"));
+                        let rest = &contents.as_bytes()[start..];
+                        let mut response = format!(
+                            "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\n\r\n",
+                            rest.len()
+                        )
+                        .into_bytes();
+                        response.extend_from_slice(rest);
+                        response
+                    }
+                    // the compressed body is cut off half way through
+                    None => {
+                        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                        encoder.write_all(contents.as_bytes()).unwrap();
+                        let body = encoder.finish().unwrap();
+                        let mut response = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\
+                            Content-Encoding: gzip\r\nAccept-Ranges: bytes\r\n\r\n",
+                            body.len()
+                        )
+                        .into_bytes();
+                        response.extend_from_slice(&body[..body.len() / 2]);
+                        response
+                    }
+                };
+                let _ = stream.write_all(&response);
+            }
+        });
+        let mut body = String::new();
+        let err = Input::new(with_config(&url, retries(0))?)?
+            .read_to_string(&mut body)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+
+        let mut body = String::new();
+        Input::new(with_config(&url, retries(1))?)?.read_to_string(&mut body)?;
+        assert_eq!(body, contents);
+        Ok(())
+    }
+}
@@ -0,0 +1,199 @@
+use crate::http::{HttpBackend, HttpRequest, HttpResponse};
+use crate::{Error, Result};
+use reqwest::blocking::{Body, Client, RequestBuilder, Response};
+use reqwest::{Certificate, Method, Proxy};
+use std::fs;
+use std::io::{self, Cursor, Error as IoError, ErrorKind, Read};
+use std::panic::resume_unwind;
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::thread::spawn;
+
+/// The [`HttpBackend`] using the blocking client of [reqwest](https://docs.rs/reqwest)
+///
+/// By default a client is built for each request from its [`HttpConfig`](crate::HttpConfig).
+/// An existing client can be used with [`with_client`](ReqwestBackend::with_client).
+#[derive(Debug, Default, Clone)]
+pub struct ReqwestBackend {
+    client: Option<Client>,
+}
+
+impl ReqwestBackend {
+    /// A backend that builds a client for each request
+    pub fn new() -> Self {
+        ReqwestBackend::default()
+    }
+
+    /// A backend that sends every request with `client`
+    ///
+    /// The timeouts, proxy, certificates and user agent of the client are used instead
+    /// of those from the [`HttpConfig`](crate::HttpConfig), its headers are still sent.
+    pub fn with_client(client: Client) -> Self {
+        ReqwestBackend {
+            client: Some(client),
+        }
+    }
+
+    fn build(&self, request: &HttpRequest<'_>) -> Result<RequestBuilder> {
+        let client = match &self.client {
+            Some(client) => client.clone(),
+            None => client(request)?,
+        };
+        let method = Method::from_bytes(request.method().as_bytes()).map_err(|_| Error::Http {
+            code: 400,
+            message: format!("invalid method {}", request.method()),
+        })?;
+        let mut req = client.request(method, request.url());
+        for (name, value) in request.headers() {
+            req = req.header(name, value);
+        }
+        Ok(req)
+    }
+}
+
+impl HttpBackend for ReqwestBackend {
+    fn get(&self, request: &HttpRequest<'_>) -> Result<HttpResponse> {
+        Ok(response(self.build(request)?.send()?))
+    }
+
+    /// The request is sent on another thread as reqwest needs a body it owns,
+    /// each chunk of `body` is read as that thread asks for it.
+    fn send(
+        &self,
+        request: &HttpRequest<'_>,
+        body: &mut dyn Read,
+        size: Option<u64>,
+    ) -> Result<HttpResponse> {
+        let req = self.build(request)?;
+        let (tx, rx) = sync_channel(0);
+        let reader = ChannelReader {
+            rx,
+            chunk: Cursor::new(Vec::new()),
+        };
+        let sending = spawn(move || {
+            let body = match size {
+                Some(size) => Body::sized(reader, size),
+                None => Body::new(reader),
+            };
+            req.body(body).send()
+        });
+
+        // an empty chunk first so nothing is read from `body` until the request is ready for it
+        let mut chunk = Vec::new();
+        let mut read_error = None;
+        while tx.send(Ok(chunk)).is_ok() {
+            let mut buffer = vec![0; 64 * 1024];
+            match body.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(len) => {
+                    buffer.truncate(len);
+                    chunk = buffer;
+                }
+                Err(err) if err.kind() == ErrorKind::Interrupted => chunk = Vec::new(),
+                Err(err) => {
+                    let _ = tx.send(Err(IoError::new(err.kind(), err.to_string())));
+                    read_error = Some(err);
+                    break;
+                }
+            }
+        }
+        drop(tx);
+
+        let result = sending.join().unwrap_or_else(|panic| resume_unwind(panic));
+        if let Some(err) = read_error {
+            return Err(err.into());
+        }
+        Ok(response(result?))
+    }
+
+    fn head(&self, request: &HttpRequest<'_>) -> Result<HttpResponse> {
+        Ok(response(self.build(request)?.send()?))
+    }
+}
+
+/// The body of a request, read from the chunks sent down a channel
+struct ChannelReader {
+    rx: Receiver<io::Result<Vec<u8>>>,
+    chunk: Cursor<Vec<u8>>,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        while self.chunk.position() >= self.chunk.get_ref().len() as u64 {
+            match self.rx.recv() {
+                Ok(chunk) => self.chunk = Cursor::new(chunk?),
+                // the whole body has been sent
+                Err(_) => return Ok(0),
+            }
+        }
+        self.chunk.read(buffer)
+    }
+}
+
+fn response(resp: Response) -> HttpResponse {
+    let headers = resp
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+            (name.as_str().to_owned(), value)
+        })
+        .collect();
+    HttpResponse {
+        url: resp.url().to_string(),
+        status: resp.status().as_u16(),
+        headers,
+        body: Box::new(ResponseBody(resp)),
+    }
+}
+
+/// The body of a response, a connection that closes part way through is an
+/// [`UnexpectedEof`](ErrorKind::UnexpectedEof) error as it is with the other backends
+struct ResponseBody(Response);
+
+impl Read for ResponseBody {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buffer).map_err(|err| match err.kind() {
+            ErrorKind::Other => IoError::new(ErrorKind::UnexpectedEof, err),
+            _ => err,
+        })
+    }
+}
+
+/// Builds a client set up from the request's config
+fn client(request: &HttpRequest<'_>) -> Result<Client> {
+    // the default of 30 seconds is turned off to match the other backends
+    let mut builder = Client::builder().timeout(request.read_timeout());
+    if let Some(timeout) = request.connect_timeout() {
+        builder = builder.connect_timeout(timeout);
+    }
+    if let Some(user_agent) = request.user_agent() {
+        builder = builder.user_agent(user_agent);
+    }
+    // the config has already read the proxy environment variables
+    builder = match request.proxy() {
+        Some(proxy) => builder.proxy(Proxy::all(proxy)?),
+        None => builder.no_proxy(),
+    };
+    if let Some(ca_bundle) = request.ca_bundle() {
+        builder = builder.tls_built_in_root_certs(false);
+        for cert in Certificate::from_pem_bundle(&fs::read(ca_bundle)?)? {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    Ok(builder.build()?)
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        match err.status() {
+            Some(status) => Error::Http {
+                code: status.as_u16(),
+                message: err.to_string(),
+            },
+            None if err.is_timeout() => {
+                Error::Io(IoError::new(ErrorKind::TimedOut, err.to_string()))
+            }
+            None => crate::http::transport_error(&err),
+        }
+    }
+}
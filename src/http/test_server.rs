@@ -0,0 +1,87 @@
+//! A tiny local HTTP server for the tests of the HTTP backends
+
+use std::io::{BufRead, BufReader};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread::spawn;
+use tiny_http::{Header, Request, Response, Server};
+
+/// Starts a server on a random local port that passes each request to `handler`
+/// and returns the url of its root
+pub(crate) fn serve<F>(handler: F) -> String
+where
+    F: Fn(Request) + Send + Sync + 'static,
+{
+    let server = Arc::new(Server::http("127.0.0.1:0").unwrap());
+    let url = format!("http://{}/", server.server_addr().to_ip().unwrap());
+    spawn(move || {
+        for request in server.incoming_requests() {
+            handler(request);
+        }
+    });
+    url
+}
+
+/// Serves `data` for every GET request, honouring `Range: bytes=N-` if `ranges` is true
+pub(crate) fn serve_bytes(data: Vec<u8>, ranges: bool) -> String {
+    serve(move |request| {
+        let start = request_header(&request, "range");
+        let start = start
+            .as_deref()
+            .and_then(|range| range.strip_prefix("bytes="))
+            .and_then(|r| r.strip_suffix('-'))
+            .and_then(|r| r.parse::<usize>().ok());
+        let response = match start {
+            Some(start) if ranges && start >= data.len() => Response::from_data(vec![])
+                .with_status_code(416)
+                .with_header(header("Content-Range", &format!("bytes */{}", data.len()))),
+            Some(start) if ranges => Response::from_data(data[start..].to_vec())
+                .with_status_code(206)
+                .with_header(header(
+                    "Content-Range",
+                    &format!("bytes {}-{}/{}", start, data.len() - 1, data.len()),
+                )),
+            _ => Response::from_data(data.clone()),
+        };
+        let response = if ranges {
+            response.with_header(header("Accept-Ranges", "bytes"))
+        } else {
+            response
+        };
+        let _ = request.respond(response);
+    })
+}
+
+pub(crate) fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).unwrap()
+}
+
+/// The value of the header of `request` called `name`, ignoring case
+pub(crate) fn request_header(request: &Request, name: &'static str) -> Option<String> {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv(name))
+        .map(|h| h.value.to_string())
+}
+
+/// Starts a plain TCP server for tests that need to send responses tiny_http won't,
+/// such as a body shorter than its `Content-Length`.
+/// `handler` is given the connection and the head of the request.
+pub(crate) fn serve_raw<F>(handler: F) -> String
+where
+    F: Fn(TcpStream, String) + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    spawn(move || {
+        for stream in listener.incoming() {
+            let stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut head = String::new();
+            while reader.read_line(&mut head).unwrap_or(0) > 2 && !head.ends_with("\r\n\r\n") {}
+            handler(stream, head);
+        }
+    });
+    url
+}
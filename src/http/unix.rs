@@ -0,0 +1,348 @@
+use crate::http::{percent_decode, HttpBackend, HttpRequest, HttpResponse};
+use crate::{Error, Result};
+use std::ffi::OsStr;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use url::Url;
+
+/// The most redirects followed for one request
+const MAX_REDIRECTS: usize = 5;
+
+/// Returns true for urls like `http+unix://%2Frun%2Fapp.sock/path` where the host
+/// is the percent encoded path of a Unix domain socket
+pub(crate) fn is_unix(url: &str) -> bool {
+    url.starts_with("http+unix://")
+}
+
+/// The path of the socket a `http+unix://` url is sent to
+pub(crate) fn socket_path(url: &Url) -> PathBuf {
+    let socket = percent_decode(url.host_str().unwrap_or_default());
+    PathBuf::from(OsStr::from_bytes(&socket))
+}
+
+/// Sends HTTP/1.1 requests over Unix domain sockets, which none of the built in clients can do
+///
+/// It is always used for `http+unix://` urls, whatever the backend is.
+pub(crate) struct UnixSocketBackend;
+
+impl HttpBackend for UnixSocketBackend {
+    fn get(&self, request: &HttpRequest<'_>) -> Result<HttpResponse> {
+        follow_redirects(request, |url| exchange(request, url, None))
+    }
+
+    fn send(
+        &self,
+        request: &HttpRequest<'_>,
+        body: &mut dyn Read,
+        size: Option<u64>,
+    ) -> Result<HttpResponse> {
+        // the body can only be sent once so redirects are not followed
+        exchange(request, &Url::parse(request.url())?, Some((body, size)))
+    }
+
+    fn head(&self, request: &HttpRequest<'_>) -> Result<HttpResponse> {
+        follow_redirects(request, |url| exchange(request, url, None))
+    }
+}
+
+fn follow_redirects<F>(request: &HttpRequest<'_>, mut exchange: F) -> Result<HttpResponse>
+where
+    F: FnMut(&Url) -> Result<HttpResponse>,
+{
+    let mut url = Url::parse(request.url())?;
+    for _ in 0..MAX_REDIRECTS {
+        let resp = exchange(&url)?;
+        let location = match (resp.status, resp.header("location")) {
+            (301 | 302 | 303 | 307 | 308, Some(location)) => url.join(location)?,
+            _ => return Ok(resp),
+        };
+        if !is_unix(location.as_str()) {
+            return Err(Error::other(
+                "a http+unix url can only redirect to the same socket",
+            ));
+        }
+        url = location;
+    }
+    Err(Error::other("too many redirects"))
+}
+
+/// Sends one request on a new connection and reads the head of the response
+fn exchange(
+    request: &HttpRequest<'_>,
+    url: &Url,
+    body: Option<(&mut dyn Read, Option<u64>)>,
+) -> Result<HttpResponse> {
+    let stream = UnixStream::connect(socket_path(url))?;
+    stream.set_read_timeout(request.read_timeout())?;
+    stream.set_write_timeout(request.read_timeout())?;
+
+    let mut target = url.path().to_owned();
+    if let Some(query) = url.query() {
+        target.push('?');
+        target.push_str(query);
+    }
+    let mut head = format!(
+        "{} {target} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n",
+        request.method()
+    );
+    for (name, value) in request.headers() {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    let mut writer = io::BufWriter::new(&stream);
+    let sent = match body {
+        Some((body, Some(size))) => {
+            head.push_str(&format!("Content-Length: {size}\r\n\r\n"));
+            writer
+                .write_all(head.as_bytes())
+                .and_then(|_| io::copy(&mut body.take(size), &mut writer).map(drop))
+        }
+        Some((body, None)) => {
+            head.push_str("Transfer-Encoding: chunked\r\n\r\n");
+            writer
+                .write_all(head.as_bytes())
+                .and_then(|_| write_chunked(body, &mut writer))
+        }
+        None => writer.write_all(format!("{head}\r\n").as_bytes()),
+    }
+    .and_then(|_| writer.flush());
+    drop(writer);
+
+    // the server may have answered before reading all of the body, e.g. to refuse it
+    match (read_response(request.method(), url, stream), sent) {
+        (Ok(resp), _) => Ok(resp),
+        (Err(_), Err(err)) => Err(err.into()),
+        (Err(err), Ok(())) => Err(err),
+    }
+}
+
+fn write_chunked(body: &mut dyn Read, writer: &mut dyn Write) -> io::Result<()> {
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let len = match body.read(&mut buffer) {
+            Ok(0) => return writer.write_all(b"0\r\n\r\n"),
+            Ok(len) => len,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        writer.write_all(format!("{len:x}\r\n").as_bytes())?;
+        writer.write_all(&buffer[..len])?;
+        writer.write_all(b"\r\n")?;
+    }
+}
+
+fn read_response(method: &str, url: &Url, stream: UnixStream) -> Result<HttpResponse> {
+    let mut reader = BufReader::new(stream);
+    let (status, headers) = loop {
+        let (status, headers) = read_head(&mut reader)?;
+        // skip `100 Continue` and other informational responses
+        if !(100..200).contains(&status) {
+            break (status, headers);
+        }
+    };
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    };
+    let body: Box<dyn Read + Send> = if method == "HEAD" || status == 204 || status == 304 {
+        Box::new(io::empty())
+    } else if header("transfer-encoding").map_or(false, |te| te.contains("chunked")) {
+        Box::new(Chunked::new(reader))
+    } else if let Some(len) = header("content-length").and_then(|len| len.trim().parse().ok()) {
+        Box::new(reader.take(len))
+    } else {
+        Box::new(reader)
+    };
+    Ok(HttpResponse::new(url.as_str(), status, headers, body))
+}
+
+/// Reads the status line and headers of a response
+fn read_head(reader: &mut impl BufRead) -> Result<(u16, Vec<(String, String)>)> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let status = line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| invalid_response(&line))?;
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid_response("the headers were cut short"));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            return Ok((status, headers));
+        }
+        let (name, value) = line.split_once(':').ok_or_else(|| invalid_response(line))?;
+        headers.push((name.trim().to_owned(), value.trim().to_owned()));
+    }
+}
+
+fn invalid_response(line: &str) -> Error {
+    Error::Io(io::Error::new(
+        ErrorKind::InvalidData,
+        format!("invalid HTTP response: {line}"),
+    ))
+}
+
+/// Decodes a body sent with `Transfer-Encoding: chunked`
+struct Chunked<R> {
+    reader: R,
+    /// what is left of the current chunk, `None` once the last chunk has been read
+    remaining: Option<u64>,
+}
+
+impl<R: BufRead> Chunked<R> {
+    fn new(reader: R) -> Self {
+        Chunked {
+            reader,
+            remaining: Some(0),
+        }
+    }
+
+    /// Reads the size line of the next chunk, and the trailers after the last one
+    fn next_chunk(&mut self) -> io::Result<Option<u64>> {
+        let mut line = String::new();
+        self.reader.read_line(&mut line)?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = u64::from_str_radix(size, 16).map_err(|_| {
+            io::Error::new(ErrorKind::InvalidData, format!("invalid chunk size {size}"))
+        })?;
+        if size > 0 {
+            return Ok(Some(size));
+        }
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Read for Chunked<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let remaining = match self.remaining {
+            None => return Ok(0),
+            Some(0) => match self.next_chunk()? {
+                Some(size) => size,
+                None => {
+                    self.remaining = None;
+                    return Ok(0);
+                }
+            },
+            Some(remaining) => remaining,
+        };
+        let max = buffer
+            .len()
+            .min(usize::try_from(remaining).unwrap_or(usize::MAX));
+        let read = self.reader.read(&mut buffer[..max])?;
+        if read == 0 {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        let remaining = remaining - read as u64;
+        if remaining == 0 {
+            let mut crlf = String::new();
+            self.reader.read_line(&mut crlf)?;
+        }
+        self.remaining = Some(remaining);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CachedInput, ClioPath, Input, Output};
+    use std::collections::HashMap;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use std::thread::spawn;
+    use tiny_http::{Method, Response, Server};
+
+    #[test]
+    fn test_chunked() -> io::Result<()> {
+        let body = "5;ext=1\r\nhello\r\n7\r\n, world\r\n0\r\nTrailer: yes\r\n\r\n";
+        let mut decoded = String::new();
+        Chunked::new(body.as_bytes()).read_to_string(&mut decoded)?;
+        assert_eq!(decoded, "hello, world");
+
+        let mut encoded = Vec::new();
+        write_chunked(&mut &b"hello"[..], &mut encoded)?;
+        assert_eq!(encoded, b"5\r\nhello\r\n0\r\n\r\n");
+
+        let err = Chunked::new(&b"5\r\nhel"[..]).read_to_end(&mut Vec::new());
+        assert_eq!(err.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        Ok(())
+    }
+
+    /// Starts a server on a socket in a temporary directory, which stores the body of each
+    /// `PUT` and sends it back for a `GET`, returning the `http+unix` url of its root
+    fn serve_unix(dir: &Path) -> String {
+        let socket = dir.join("app.sock");
+        let server = Server::http_unix(&socket).unwrap();
+        let files = Arc::new(Mutex::new(HashMap::new()));
+        spawn(move || {
+            for mut request in server.incoming_requests() {
+                let url = request.url().to_owned();
+                let mut files = files.lock().unwrap();
+                let response = match request.method() {
+                    Method::Put => {
+                        let mut body = Vec::new();
+                        request.as_reader().read_to_end(&mut body).unwrap();
+                        files.insert(url, body);
+                        Response::from_data(vec![]).with_status_code(201)
+                    }
+                    _ if url == "/old" => Response::from_data(vec![])
+                        .with_status_code(301)
+                        .with_header(
+                            tiny_http::Header::from_bytes("Location", "/file.txt").unwrap(),
+                        ),
+                    _ => match files.get(&url) {
+                        Some(body) => Response::from_data(body.clone()),
+                        None => Response::from_data(vec![]).with_status_code(404),
+                    },
+                };
+                let _ = request.respond(response);
+            }
+        });
+        let socket = socket.to_str().unwrap().replace('/', "%2F");
+        format!("http+unix://{socket}/")
+    }
+
+    #[test]
+    fn test_unix_socket() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let url = serve_unix(tmp.path());
+        let file = format!("{url}file.txt");
+
+        let mut output = Output::new(&file)?;
+        output.write_all(b"hello over a socket")?;
+        output.finish()?;
+        let mut output = ClioPath::new(format!("{url}sized.txt"))?.create_with_len(5)?;
+        output.write_all(b"sized")?;
+        output.finish()?;
+
+        let mut input = Input::new(&file)?;
+        let mut contents = String::new();
+        input.read_to_string(&mut contents)?;
+        assert_eq!(contents, "hello over a socket");
+        assert_eq!(input.http_metadata().unwrap().url(), file);
+
+        let cached = CachedInput::new(&format!("{url}sized.txt"))?;
+        assert_eq!(cached.get_data(), b"sized");
+
+        let mut contents = String::new();
+        Input::new(&format!("{url}old"))?.read_to_string(&mut contents)?;
+        assert_eq!(contents, "hello over a socket");
+
+        let err = Input::new(&format!("{url}missing")).unwrap_err();
+        assert!(matches!(err, Error::Http { code: 404, .. }));
+        Ok(())
+    }
+}
@@ -0,0 +1,199 @@
+use crate::http::{percent_decode, send, webdav, with_retries, HttpConfig, HttpResponse};
+use crate::{ClioPath, Result};
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/// The method used to upload an [`Output`](crate::Output) to a url
+///
+/// Set with [`OsStrParser::http_method`](crate::clapers::OsStrParser::http_method).
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Method {
+    /// `PUT` the body to the url
+    #[default]
+    Put,
+    /// `POST` the body to the url
+    Post,
+}
+
+impl Method {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Method::Put => "PUT",
+            Method::Post => "POST",
+        }
+    }
+}
+
+/// Everything needed to send the body of an output to a url, apart from the body
+pub(crate) struct Upload {
+    url: String,
+    method: Method,
+    headers: Vec<(String, String)>,
+    multipart: Option<Multipart>,
+    config: Arc<HttpConfig>,
+    /// where an atomic upload to a WebDAV share is moved to once it is finished,
+    /// `url` is then a temporary name next to it
+    destination: Option<String>,
+}
+
+impl Upload {
+    pub(crate) fn new(url: &Url, path: &ClioPath, mut headers: Vec<(String, String)>) -> Self {
+        let multipart = path.multipart.as_deref().map(|field| {
+            // the last segment of the url, which is still percent encoded
+            let filename = path.file_name().map(|name| {
+                String::from_utf8_lossy(&percent_decode(&*name.to_string_lossy())).into_owned()
+            });
+            Multipart::new(field, filename.as_deref())
+        });
+        if let Some(multipart) = &multipart {
+            headers.push(("Content-Type".to_owned(), multipart.content_type()));
+        }
+        let config = path.http_config();
+        // only an atomic upload to a WebDAV share needs a temporary name
+        let temp = if path.atomic && config.webdav {
+            webdav::temp_url(url.as_str()).ok()
+        } else {
+            None
+        };
+        let (url, destination) = match temp {
+            Some(temp) => (temp, Some(url.as_str().to_owned())),
+            None => (url.as_str().to_owned(), None),
+        };
+        Upload {
+            url,
+            method: path.http_method,
+            headers,
+            multipart,
+            config,
+            destination,
+        }
+    }
+
+    pub(crate) fn config(&self) -> &HttpConfig {
+        &self.config
+    }
+
+    /// Gets the server ready for the body, creating any missing parent collections on a WebDAV share
+    pub(crate) fn prepare(&self) -> Result<()> {
+        if self.config.webdav {
+            webdav::create_parents(&self.url, &self.config)?;
+        }
+        Ok(())
+    }
+
+    /// Moves an atomic upload into place once all of the body has been sent,
+    /// or throws it away if the output was not finished
+    pub(crate) fn complete(&self, resp: HttpResponse, finished: bool) -> Result<HttpResponse> {
+        match &self.destination {
+            Some(destination) if finished => webdav::move_to(&self.url, destination, &self.config)?,
+            Some(_) => webdav::delete(&self.url, &self.config),
+            None => {}
+        }
+        Ok(resp)
+    }
+
+    /// Sends `body`, which is `size` bytes long if it is known, once
+    pub(crate) fn send(&self, body: &mut dyn Read, size: Option<u64>) -> Result<HttpResponse> {
+        let method = self.method.as_str();
+        match &self.multipart {
+            Some(multipart) => {
+                let size = size.map(|size| multipart.len(size));
+                let mut body = multipart.wrap(body);
+                send(
+                    method,
+                    &self.url,
+                    &self.headers,
+                    &mut body,
+                    size,
+                    &self.config,
+                )
+            }
+            None => send(method, &self.url, &self.headers, body, size, &self.config),
+        }
+    }
+
+    /// Uploads the rest of `body`, starting again from the same position if a retry is needed
+    pub(crate) fn send_seekable<R: Read + Seek>(&self, body: &mut R) -> Result<u64> {
+        let start = body.stream_position()?;
+        let size = body.seek(SeekFrom::End(0))? - start;
+        self.prepare()?;
+        let resp = with_retries(&self.config, || {
+            body.seek(SeekFrom::Start(start))?;
+            self.send(&mut body.take(size), Some(size))
+        })?;
+        self.complete(resp, true)?;
+        Ok(size)
+    }
+}
+
+/// The parts of a `multipart/form-data` body that go before and after the file
+struct Multipart {
+    boundary: String,
+    head: Vec<u8>,
+    tail: Vec<u8>,
+}
+
+impl Multipart {
+    fn new(field: &str, filename: Option<&str>) -> Self {
+        let boundary = boundary();
+        let mut head = format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"{}\"",
+            quote(field)
+        );
+        if let Some(filename) = filename {
+            head.push_str(&format!("; filename=\"{}\"", quote(filename)));
+        }
+        head.push_str("\r\nContent-Type: application/octet-stream\r\n\r\n");
+        let tail = format!("\r\n--{boundary}--\r\n");
+        Multipart {
+            boundary,
+            head: head.into_bytes(),
+            tail: tail.into_bytes(),
+        }
+    }
+
+    fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    /// The length of the whole form with a file `size` bytes long
+    fn len(&self, size: u64) -> u64 {
+        self.head.len() as u64 + size + self.tail.len() as u64
+    }
+
+    fn wrap<'a>(&'a self, body: &'a mut dyn Read) -> impl Read + 'a {
+        Cursor::new(&self.head[..])
+            .chain(body)
+            .chain(Cursor::new(&self.tail[..]))
+    }
+}
+
+/// A boundary that is different for every form, so it is very unlikely to be in the file
+fn boundary() -> String {
+    format!("clio-{}", unique_id())
+}
+
+/// An id that is different every time, from the time, the process and a counter
+pub(crate) fn unique_id() -> String {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos());
+    format!(
+        "{:x}-{:x}-{:x}",
+        nanos,
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// Escapes the characters that can't go in a quoted parameter the same way browsers do
+fn quote(value: &str) -> String {
+    value
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
@@ -1,142 +1,95 @@
+use crate::http::{HttpBackend, HttpRequest, HttpResponse};
 use crate::{Error, Result};
-use pipe::{PipeBufWriter, PipeReader};
-use std::fmt::{self, Debug};
-use std::io::{Error as IoError, ErrorKind, Read, Result as IoResult, Write};
-use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
-use std::sync::Mutex;
-use std::thread::spawn;
-
-pub struct HttpWriter {
-    write: PipeBufWriter,
-    rx: Mutex<Receiver<Result<()>>>,
-}
-
-/// A wrapper for the read end of the pipe that sniches on when data is first read
-/// by sending `Ok(())` down tx.
-///
-/// This is used so that we can block the code making the put request until ethier:
-/// a) the data is tried to be read, or
-/// b) the request fails before trying to send the payload (bad hostname, invalid auth, etc)
-struct SnitchingReader {
-    read: PipeReader,
-    connected: bool,
-    tx: SyncSender<Result<()>>,
-}
-
-impl Read for SnitchingReader {
-    fn read(&mut self, buffer: &mut [u8]) -> IoResult<usize> {
-        if !self.connected {
-            self.tx
-                .send(Ok(()))
-                .map_err(|e| IoError::new(ErrorKind::Other, e))?;
-            self.connected = true;
-        }
-        self.read.read(buffer)
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use std::sync::Arc;
+use ureq::rustls;
+
+/// The [`HttpBackend`] using [ureq](https://docs.rs/ureq)
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UreqBackend;
+
+impl HttpBackend for UreqBackend {
+    fn get(&self, request: &HttpRequest<'_>) -> Result<HttpResponse> {
+        Ok(response(build(request)?.call()?))
     }
-}
-
-impl HttpWriter {
-    pub fn new(url: &str, size: Option<u64>) -> Result<Self> {
-        let (read, write) = pipe::pipe_buffered();
 
-        let mut req = ureq::put(url);
+    fn send(
+        &self,
+        request: &HttpRequest<'_>,
+        body: &mut dyn Read,
+        size: Option<u64>,
+    ) -> Result<HttpResponse> {
+        let mut req = build(request)?;
         if let Some(size) = size {
             req = req.set("content-length", &size.to_string());
         }
-
-        let (done_tx, rx) = sync_channel(0);
-        let snitch = SnitchingReader {
-            read,
-            connected: false,
-            tx: done_tx.clone(),
-        };
-
-        spawn(move || {
-            done_tx
-                .send(req.send(snitch).map(|_| ()).map_err(|e| e.into()))
-                .unwrap();
-        });
-
-        // either Ok(()) if the other thread started reading or the connection error
-        rx.recv().unwrap()?;
-        let rx = Mutex::new(rx);
-        Ok(HttpWriter { write, rx })
+        Ok(response(req.send(body)?))
     }
 
-    pub fn finish(self) -> Result<()> {
-        drop(self.write);
-        self.rx
-            .try_lock()
-            .expect("clio HttpWriter lock should one ever be taken once while dropping")
-            .recv()
-            .unwrap()?;
-        Ok(())
+    fn head(&self, request: &HttpRequest<'_>) -> Result<HttpResponse> {
+        Ok(response(build(request)?.call()?))
     }
 }
 
-impl Write for HttpWriter {
-    fn write(&mut self, buffer: &[u8]) -> IoResult<usize> {
-        self.write.write(buffer)
-    }
-    fn flush(&mut self) -> IoResult<()> {
-        self.write.flush()
+fn response(resp: ureq::Response) -> HttpResponse {
+    let headers = resp
+        .headers_names()
+        .into_iter()
+        .filter_map(|name| {
+            let value = resp.header(&name)?.to_owned();
+            Some((name, value))
+        })
+        .collect();
+    HttpResponse {
+        url: resp.get_url().to_owned(),
+        status: resp.status(),
+        headers,
+        body: Box::new(resp.into_reader()),
     }
 }
 
-impl fmt::Debug for HttpWriter {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("HttpWriter").finish()
+/// Builds a request with an agent set up from the request's config
+fn build(request: &HttpRequest<'_>) -> Result<ureq::Request> {
+    let mut agent = ureq::AgentBuilder::new();
+    if let Some(timeout) = request.connect_timeout() {
+        agent = agent.timeout_connect(timeout);
     }
-}
-
-pub struct HttpReader {
-    length: Option<u64>,
-    #[cfg(feature = "clap-parse")]
-    read: Mutex<Box<dyn Read + Send>>,
-    #[cfg(not(feature = "clap-parse"))]
-    read: Box<dyn Read + Send>,
-}
-
-impl HttpReader {
-    pub fn new(url: &str) -> Result<Self> {
-        let resp = ureq::get(url).call()?;
-
-        let length = resp
-            .header("content-length")
-            .and_then(|x| x.parse::<u64>().ok());
-        Ok(HttpReader {
-            length,
-            #[cfg(not(feature = "clap-parse"))]
-            read: Box::new(resp.into_reader()),
-            #[cfg(feature = "clap-parse")]
-            read: Mutex::new(Box::new(resp.into_reader())),
-        })
+    if let Some(timeout) = request.read_timeout() {
+        agent = agent.timeout_read(timeout).timeout_write(timeout);
     }
-
-    pub fn len(&self) -> Option<u64> {
-        self.length
+    if let Some(user_agent) = request.user_agent() {
+        agent = agent.user_agent(user_agent);
     }
-}
-
-impl Read for HttpReader {
-    #[cfg(not(feature = "clap-parse"))]
-    fn read(&mut self, buffer: &mut [u8]) -> IoResult<usize> {
-        self.read.read(buffer)
+    if let Some(proxy) = request.proxy() {
+        agent = agent.proxy(ureq::Proxy::new(proxy)?);
     }
-
-    #[cfg(feature = "clap-parse")]
-    fn read(&mut self, buffer: &mut [u8]) -> IoResult<usize> {
-        self.read
-            .lock()
-            .map_err(|_| IoError::new(ErrorKind::Other, "Error locking HTTP reader"))?
-            .read(buffer)
+    if let Some(ca_bundle) = request.ca_bundle() {
+        agent = agent.tls_config(Arc::new(tls_config(ca_bundle)?));
+    }
+    let mut req = agent.build().request(request.method(), request.url());
+    for (name, value) in request.headers() {
+        req = req.set(name, value);
     }
+    Ok(req)
 }
 
-impl Debug for HttpReader {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("HttpReader").finish()
-    }
+/// A TLS config that only trusts the certificates in the PEM file
+///
+/// It is built with the rustls that ureq re-exports, so it is always the version ureq takes.
+fn tls_config(ca_bundle: &Path) -> Result<rustls::ClientConfig> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(ca_bundle)?))
+        .collect::<io::Result<Vec<_>>>()?;
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add_parsable_certificates(certs);
+    // ureq only enables ring, so don't rely on the process wide default provider
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    Ok(rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?
+        .with_root_certificates(roots)
+        .with_no_client_auth())
 }
 
 impl From<ureq::Error> for Error {
@@ -146,10 +99,7 @@ impl From<ureq::Error> for Error {
                 code,
                 message: resp.status_text().to_owned(),
             },
-            _ => Error::Http {
-                code: 499,
-                message: err.to_string(),
-            },
+            ureq::Error::Transport(transport) => crate::http::transport_error(&transport),
         }
     }
 }
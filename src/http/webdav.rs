@@ -0,0 +1,449 @@
+use crate::http::upload::unique_id;
+use crate::http::{send, with_retries, xml_unescape, HttpConfig};
+use crate::{Error, Result};
+use std::io::{self, Read};
+use url::Url;
+
+/// Asks for just the type of each resource, which is all that is needed to tell files from collections
+const PROPFIND_BODY: &str = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+    <propfind xmlns=\"DAV:\"><prop><resourcetype/></prop></propfind>";
+
+/// Lists the files in the collection at `url` and all the collections inside it
+///
+/// If `url` is a file rather than a collection the list is just `url`.
+pub(crate) fn list(url: &Url, config: &HttpConfig) -> Result<Vec<Url>> {
+    let mut files = Vec::new();
+    let mut collections = vec![url.clone()];
+    while let Some(collection) = collections.pop() {
+        for (member, is_collection) in propfind(&collection, config)? {
+            if same_resource(&member, &collection) {
+                if !is_collection {
+                    files.push(member);
+                }
+            } else if is_collection {
+                collections.push(member);
+            } else {
+                files.push(member);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The urls of the resources in a `PROPFIND` with `Depth: 1` and whether each is a collection
+fn propfind(url: &Url, config: &HttpConfig) -> Result<Vec<(Url, bool)>> {
+    let headers = [
+        ("Depth".to_owned(), "1".to_owned()),
+        (
+            "Content-Type".to_owned(),
+            "application/xml; charset=utf-8".to_owned(),
+        ),
+    ];
+    let resp = with_retries(config, || {
+        let mut body = PROPFIND_BODY.as_bytes();
+        send(
+            "PROPFIND",
+            url.as_str(),
+            &headers,
+            &mut body,
+            Some(PROPFIND_BODY.len() as u64),
+            config,
+        )
+    })?;
+    if resp.status != 207 {
+        return Err(Error::Http {
+            code: resp.status,
+            message: "the server did not answer PROPFIND, is it a WebDAV share?".to_owned(),
+        });
+    }
+    let mut xml = String::new();
+    resp.into_body().read_to_string(&mut xml)?;
+    responses(&xml)
+        .into_iter()
+        .map(|(href, is_collection)| Ok((url.join(&href)?, is_collection)))
+        .collect()
+}
+
+/// The `href` of each `response` in a `multistatus` body, and whether it is a collection
+///
+/// Tags are matched by their local name as servers use different prefixes for the `DAV:` namespace.
+fn responses(xml: &str) -> Vec<(String, bool)> {
+    let mut responses = Vec::new();
+    let mut href: Option<String> = None;
+    let mut is_collection = false;
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        if let Some(href) = &mut href {
+            href.push_str(&rest[..start]);
+        }
+        let end = match rest[start..].find('>') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let tag = &rest[start + 1..end];
+        rest = &rest[end + 1..];
+        let (closing, tag) = match tag.strip_prefix('/') {
+            Some(tag) => (true, tag),
+            None => (false, tag),
+        };
+        let name = tag
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default();
+        let name = name.rsplit(':').next().unwrap_or(name);
+        match (name, closing) {
+            ("response", false) => is_collection = false,
+            ("href", false) if !tag.ends_with('/') => href = Some(String::new()),
+            ("href", true) => {
+                if let Some(href) = href.take() {
+                    responses.push((xml_unescape(href.trim()), false));
+                }
+            }
+            ("collection", false) => is_collection = true,
+            ("response", true) => {
+                if let Some(response) = responses.last_mut() {
+                    response.1 = is_collection;
+                }
+            }
+            _ => {}
+        }
+    }
+    responses
+}
+
+/// Returns true if the urls differ at most by a trailing `/`
+fn same_resource(a: &Url, b: &Url) -> bool {
+    a.host() == b.host()
+        && a.port_or_known_default() == b.port_or_known_default()
+        && a.path().trim_end_matches('/') == b.path().trim_end_matches('/')
+}
+
+/// Creates the collections above `url` that do not exist yet with `MKCOL`
+pub(crate) fn create_parents(url: &str, config: &HttpConfig) -> Result<()> {
+    let parent = match parent(&Url::parse(url)?) {
+        Some(parent) => parent,
+        None => return Ok(()),
+    };
+    match mkcol(&parent, config) {
+        // 405 Method Not Allowed is the answer when the collection already exists
+        Ok(()) | Err(Error::Http { code: 405, .. }) => Ok(()),
+        // 409 Conflict means its parent is missing too
+        Err(Error::Http { code: 409, .. }) => {
+            create_parents(parent.as_str(), config)?;
+            mkcol(&parent, config)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+fn mkcol(url: &Url, config: &HttpConfig) -> Result<()> {
+    with_retries(config, || {
+        send(
+            "MKCOL",
+            url.as_str(),
+            &[],
+            &mut io::empty(),
+            Some(0),
+            config,
+        )
+    })?;
+    Ok(())
+}
+
+/// The collection containing `url`, or `None` for the root
+fn parent(url: &Url) -> Option<Url> {
+    let path = url.path().trim_end_matches('/');
+    let end = path.rfind('/')?;
+    if end == 0 {
+        return None;
+    }
+    let mut parent = url.clone();
+    parent.set_path(&path[..=end]);
+    parent.set_query(None);
+    Some(parent)
+}
+
+/// A new url in the same collection as `url` that an atomic output is uploaded to before it is moved
+pub(crate) fn temp_url(url: &str) -> Result<String> {
+    Ok(Url::parse(url)?
+        .join(&format!(".atomicwrite-{}", unique_id()))?
+        .into())
+}
+
+/// Moves the upload at `from` to `to`, replacing what is there, or deletes it if that fails
+pub(crate) fn move_to(from: &str, to: &str, config: &HttpConfig) -> Result<()> {
+    let headers = [
+        ("Destination".to_owned(), to.to_owned()),
+        ("Overwrite".to_owned(), "T".to_owned()),
+    ];
+    let moved = with_retries(config, || {
+        send("MOVE", from, &headers, &mut io::empty(), Some(0), config)
+    });
+    if let Err(err) = moved {
+        delete(from, config);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Deletes an upload that will not be used, ignoring any errors as there is nothing else to try
+pub(crate) fn delete(url: &str, config: &HttpConfig) {
+    let _ = send("DELETE", url, &[], &mut io::empty(), Some(0), config);
+}
+
+#[cfg(all(
+    test,
+    any(feature = "http-ureq", feature = "http-curl", feature = "http-reqwest")
+))]
+mod tests {
+    use super::*;
+    use crate::http::test_server::{self, header};
+    use crate::{ClioPath, Input, OutputPath};
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use std::thread::sleep;
+    use std::time::Duration;
+    use tiny_http::Response;
+
+    #[test]
+    fn test_responses() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+            <D:multistatus xmlns:D="DAV:">
+              <D:response><D:href>/dav/</D:href><D:propstat><D:prop>
+                <D:resourcetype><D:collection/></D:resourcetype>
+              </D:prop></D:propstat></D:response>
+              <response xmlns="DAV:"><href>/dav/a%20b.txt?x=1&amp;y=2</href><propstat><prop>
+                <resourcetype/>
+              </prop></propstat></response>
+              <lp1:response xmlns:lp1="DAV:"><lp1:href>http://host/dav/sub/</lp1:href>
+                <lp1:propstat><lp1:prop><lp1:resourcetype><lp1:collection />
+                </lp1:resourcetype></lp1:prop></lp1:propstat></lp1:response>
+            </D:multistatus>"#;
+        assert_eq!(
+            responses(xml),
+            [
+                ("/dav/".to_owned(), true),
+                ("/dav/a%20b.txt?x=1&y=2".to_owned(), false),
+                ("http://host/dav/sub/".to_owned(), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parent() {
+        let parent = |url: &str| parent(&Url::parse(url).unwrap()).map(String::from);
+        assert_eq!(
+            parent("http://host/a/b/c.txt?q").as_deref(),
+            Some("http://host/a/b/")
+        );
+        assert_eq!(
+            parent("http://host/a/b/").as_deref(),
+            Some("http://host/a/")
+        );
+        assert_eq!(parent("http://host/a").as_deref(), None);
+        assert_eq!(parent("http://host/").as_deref(), None);
+    }
+
+    /// The collections and files of a WebDAV share, and each request made to it
+    #[derive(Default)]
+    struct Share {
+        collections: BTreeSet<String>,
+        files: BTreeMap<String, Vec<u8>>,
+        log: Vec<String>,
+    }
+
+    /// A WebDAV share at the root of a local server, that only has the root collection to start with
+    fn serve_webdav() -> (String, Arc<Mutex<Share>>) {
+        let share = Arc::new(Mutex::new(Share::default()));
+        share.lock().unwrap().collections.insert("/".to_owned());
+        let url = test_server::serve({
+            let share = share.clone();
+            move |mut request| {
+                let mut share = share.lock().unwrap();
+                let path = request.url().to_owned();
+                let method = request.method().as_str().to_owned();
+                share.log.push(format!("{method} {path}"));
+                let parent = parent(&Url::parse(&format!("http://host{path}")).unwrap())
+                    .map_or("/".to_owned(), |parent| parent.path().to_owned());
+                let has_parent = share.collections.contains(&parent);
+                let status = match method.as_str() {
+                    "MKCOL" if share.collections.contains(&path) => 405,
+                    "MKCOL" if !has_parent => 409,
+                    "MKCOL" => {
+                        share.collections.insert(path);
+                        201
+                    }
+                    "PUT" if !has_parent => 409,
+                    "PUT" => {
+                        let mut body = Vec::new();
+                        request.as_reader().read_to_end(&mut body).unwrap();
+                        share.files.insert(path, body);
+                        201
+                    }
+                    "MOVE" => {
+                        let destination = test_server::request_header(&request, "destination")
+                            .map(|destination| Url::parse(&destination).unwrap())
+                            .unwrap();
+                        match share.files.remove(&path) {
+                            Some(body) => {
+                                share.files.insert(destination.path().to_owned(), body);
+                                204
+                            }
+                            None => 404,
+                        }
+                    }
+                    "DELETE" => share.files.remove(&path).map_or(404, |_| 204),
+                    "GET" => {
+                        let body = share.files.get(&path).cloned();
+                        let response = match body {
+                            Some(body) => Response::from_data(body),
+                            None => Response::from_data(vec![]).with_status_code(404),
+                        };
+                        let _ = request.respond(response);
+                        return;
+                    }
+                    "PROPFIND" => {
+                        let members: Vec<_> = if share.collections.contains(&path) {
+                            let children = share
+                                .collections
+                                .iter()
+                                .map(|path| (path, true))
+                                .chain(share.files.keys().map(|path| (path, false)))
+                                .filter(|(member, _)| {
+                                    let rest = member.strip_prefix(&path).unwrap_or_default();
+                                    !rest.is_empty() && !rest.trim_end_matches('/').contains('/')
+                                })
+                                .map(|(member, is_collection)| (member.clone(), is_collection));
+                            std::iter::once((path.clone(), true))
+                                .chain(children)
+                                .collect()
+                        } else if share.files.contains_key(&path) {
+                            vec![(path.clone(), false)]
+                        } else {
+                            vec![]
+                        };
+                        let mut xml = r#"<D:multistatus xmlns:D="DAV:">"#.to_owned();
+                        for (member, is_collection) in &members {
+                            let resource_type = if *is_collection {
+                                "<D:collection/>"
+                            } else {
+                                ""
+                            };
+                            xml.push_str(&format!(
+                                "<D:response><D:href>{member}</D:href><D:propstat><D:prop>\
+                                <D:resourcetype>{resource_type}</D:resourcetype>\
+                                </D:prop></D:propstat></D:response>"
+                            ));
+                        }
+                        xml.push_str("</D:multistatus>");
+                        let status = if members.is_empty() { 404 } else { 207 };
+                        let response = Response::from_string(xml)
+                            .with_status_code(status)
+                            .with_header(header("Content-Type", "application/xml"));
+                        let _ = request.respond(response);
+                        return;
+                    }
+                    _ => 405,
+                };
+                let _ = request.respond(Response::from_data(vec![]).with_status_code(status));
+            }
+        });
+        (url, share)
+    }
+
+    fn dav_path(url: &str) -> Result<ClioPath> {
+        let mut path = ClioPath::new(url)?;
+        path.http_config = Some(Arc::new(HttpConfig::default().webdav(true)));
+        Ok(path)
+    }
+
+    fn put(path: ClioPath, contents: &str) -> Result<()> {
+        let mut output = OutputPath::new(path)?.create()?;
+        output.write_all(contents.as_bytes())?;
+        output.finish()
+    }
+
+    #[test]
+    fn test_webdav() -> Result<()> {
+        let (url, share) = serve_webdav();
+        put(dav_path(&format!("{url}a/b/one.txt"))?, "one")?;
+        put(dav_path(&format!("{url}a/b/two%20words.txt"))?, "two")?;
+        put(dav_path(&format!("{url}a/three.txt"))?, "three")?;
+        assert_eq!(
+            share.lock().unwrap().collections,
+            BTreeSet::from(["/".to_owned(), "/a/".to_owned(), "/a/b/".to_owned()])
+        );
+        let mut contents = String::new();
+        Input::new(dav_path(&format!("{url}a/b/one.txt"))?)?.read_to_string(&mut contents)?;
+        assert_eq!(contents, "one");
+
+        let files: Vec<_> = dav_path(&format!("{url}a/"))?
+            .files(|_| true)?
+            .iter()
+            .map(|path| path.to_string_lossy().replace(&url, "/"))
+            .collect();
+        assert_eq!(
+            files,
+            ["/a/b/one.txt", "/a/b/two%20words.txt", "/a/three.txt"]
+        );
+        let files = dav_path(&format!("{url}a/three.txt"))?.files(|_| true)?;
+        assert_eq!(files.len(), 1);
+        // without the webdav option a url is just a single file
+        let files = ClioPath::new(format!("{url}a/"))?.files(|_| true)?;
+        assert_eq!(files.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_webdav_atomic() -> Result<()> {
+        let (url, share) = serve_webdav();
+        let mut path = dav_path(&format!("{url}file.txt"))?;
+        put(path.clone(), "old")?;
+        // only an atomic output goes to a temporary name first
+        assert!(share
+            .lock()
+            .unwrap()
+            .log
+            .iter()
+            .all(|line| !line.contains(".atomicwrite-")));
+        path.atomic = true;
+
+        let mut output = OutputPath::new(path.clone())?.create()?;
+        output.write_all(b"new")?;
+        output.finish()?;
+        {
+            let share = share.lock().unwrap();
+            assert_eq!(share.files.keys().collect::<Vec<_>>(), ["/file.txt"]);
+            assert_eq!(share.files["/file.txt"], b"new");
+            let moves = share
+                .log
+                .iter()
+                .filter(|line| line.starts_with("MOVE /.atomicwrite-"));
+            assert_eq!(moves.count(), 1);
+        }
+
+        // an output that is never finished is thrown away
+        let mut output = OutputPath::new(path)?.create()?;
+        output.write_all(b"broken")?;
+        drop(output);
+        let deleted = |share: &Share| {
+            share
+                .log
+                .iter()
+                .any(|line| line.starts_with("DELETE /.atomicwrite-"))
+        };
+        for _ in 0..100 {
+            if deleted(&share.lock().unwrap()) {
+                break;
+            }
+            sleep(Duration::from_millis(10));
+        }
+        let share = share.lock().unwrap();
+        assert!(deleted(&share));
+        assert_eq!(share.files.keys().collect::<Vec<_>>(), ["/file.txt"]);
+        assert_eq!(share.files["/file.txt"], b"new");
+        Ok(())
+    }
+}
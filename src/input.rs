@@ -1,8 +1,15 @@
+#[cfg(feature = "archive")]
+use crate::archive::ArchiveReader;
+#[cfg(feature = "compression")]
+use crate::compression::{Codec, Decoder};
 #[cfg(feature = "http")]
-use crate::http::HttpReader;
+use crate::http::{Cached, HttpReader};
 use crate::path::{ClioPathEnum, InOut};
+#[cfg(feature = "http")]
+use crate::HttpMetadata;
 use crate::{
-    assert_exists, assert_not_dir, assert_readable, impl_try_from, is_fifo, ClioPath, Error, Result,
+    assert_exists, assert_not_dir, assert_readable, impl_try_from, is_fifo, ClioPath, DataMetadata,
+    Error, Result,
 };
 use is_terminal::IsTerminal;
 use std::convert::TryFrom;
@@ -33,6 +40,8 @@ use std::io::{self, BufRead, BufReader, Cursor, Read, Result as IoResult, Seek,
 pub struct Input {
     path: ClioPath,
     stream: InputStream,
+    #[cfg(feature = "http")]
+    http_metadata: Option<HttpMetadata>,
 }
 #[derive(Debug)]
 enum InputStream {
@@ -42,10 +51,70 @@ enum InputStream {
     Pipe(File),
     /// a normal [`File`] opened from the path
     File(File),
+    /// the decoded contents of a `data:` url
+    Data(Cursor<Vec<u8>>),
     #[cfg(feature = "http")]
     #[cfg_attr(docsrs, doc(cfg(feature = "http")))]
     /// a reader that will download response from the HTTP server
-    Http(HttpReader),
+    Http(Box<HttpReader>),
+    #[cfg(feature = "archive")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "archive")))]
+    /// a reader for a single member of a tar or zip archive
+    Archive(Box<ArchiveReader>),
+    #[cfg(feature = "compression")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
+    /// one of the other streams wrapped in a decoder that decompresses it
+    Compressed(Box<Decoder<BufReader<InputStream>>>),
+}
+
+impl InputStream {
+    /// Wraps the stream in a [`Decoder`] if the extension says it is compressed,
+    /// or if it is stdin or a pipe so that the magic bytes can be checked
+    #[cfg(feature = "compression")]
+    fn decompress(self, path: &ClioPath) -> Result<Self> {
+        if path.raw {
+            return Ok(self);
+        }
+        match Codec::from_extension(path.extension()) {
+            Some(codec) => {
+                let decoder = Decoder::new(Some(codec), BufReader::new(self))?;
+                Ok(InputStream::Compressed(Box::new(decoder)))
+            }
+            None => Ok(self.sniff()),
+        }
+    }
+
+    /// Wraps stdin or a pipe in a [`Decoder`] that checks the magic bytes
+    #[cfg(feature = "compression")]
+    fn sniff(self) -> Self {
+        let sniff = match &self {
+            InputStream::Stdin(stdin) => !stdin.is_terminal(),
+            InputStream::Pipe(_) => true,
+            _ => false,
+        };
+        if sniff {
+            InputStream::Compressed(Box::new(Decoder::sniff(BufReader::new(self))))
+        } else {
+            self
+        }
+    }
+}
+
+impl Read for InputStream {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        match self {
+            InputStream::Stdin(stdin) => stdin.read(buf),
+            InputStream::Pipe(pipe) => pipe.read(buf),
+            InputStream::File(file) => file.read(buf),
+            InputStream::Data(data) => data.read(buf),
+            #[cfg(feature = "http")]
+            InputStream::Http(reader) => reader.read(buf),
+            #[cfg(feature = "archive")]
+            InputStream::Archive(reader) => reader.read(buf),
+            #[cfg(feature = "compression")]
+            InputStream::Compressed(reader) => reader.read(buf),
+        }
+    }
 }
 
 impl Input {
@@ -55,8 +124,13 @@ impl Input {
         crate::Error: From<<S as TryInto<ClioPath>>::Error>,
     {
         let path = path.try_into()?;
+        #[cfg(feature = "http")]
+        let mut cached_metadata = None;
         let stream = match &path.path {
-            ClioPathEnum::Std(_) => InputStream::Stdin(io::stdin()),
+            ClioPathEnum::Std(std) => match std.stream(InOut::In) {
+                InOut::In => InputStream::Stdin(io::stdin()),
+                _ => return Err(Error::std_direction_error()),
+            },
             ClioPathEnum::Local(file_path) => {
                 let file = File::open(file_path)?;
                 if file.metadata()?.is_dir() {
@@ -68,17 +142,57 @@ impl Input {
                     InputStream::File(file)
                 }
             }
+            ClioPathEnum::Data(data) => InputStream::Data(Cursor::new(data.data.clone())),
+            #[cfg(unix)]
+            ClioPathEnum::Fd(fd) => {
+                let file = fd.open_read()?;
+                if is_fifo(&file.metadata()?) {
+                    InputStream::Pipe(file)
+                } else {
+                    InputStream::File(file)
+                }
+            }
             #[cfg(feature = "http")]
-            ClioPathEnum::Http(url) => InputStream::Http(HttpReader::new(url.as_str())?),
+            ClioPathEnum::Http(url) => {
+                let config = path.http_config();
+                match crate::http::open_cached(url.as_str(), &config)? {
+                    Some(Cached::File(file, metadata)) => {
+                        cached_metadata = Some(metadata);
+                        InputStream::File(file)
+                    }
+                    Some(Cached::Uncacheable(reader)) => InputStream::Http(Box::new(reader)),
+                    None => InputStream::Http(Box::new(HttpReader::new(url.as_str(), config)?)),
+                }
+            }
+            #[cfg(feature = "archive")]
+            ClioPathEnum::Archive(archive) => InputStream::Archive(Box::new(archive.open()?)),
         };
-        Ok(Input { path, stream })
+        #[cfg(feature = "http")]
+        let http_metadata = match &stream {
+            InputStream::Http(reader) => Some(reader.metadata().clone()),
+            _ => cached_metadata,
+        };
+        #[cfg(feature = "compression")]
+        let stream = stream.decompress(&path)?;
+        Ok(Input {
+            path,
+            stream,
+            #[cfg(feature = "http")]
+            http_metadata,
+        })
     }
 
     /// Constructs a new input for stdin
     pub fn std() -> Self {
+        let stream = InputStream::Stdin(io::stdin());
+        // the same as `Input::new("-")`, which has no extension so can only sniff the magic
+        #[cfg(feature = "compression")]
+        let stream = stream.sniff();
         Input {
             path: ClioPath::std().with_direction(InOut::In),
-            stream: InputStream::Stdin(io::stdin()),
+            stream,
+            #[cfg(feature = "http")]
+            http_metadata: None,
         }
     }
 
@@ -91,8 +205,8 @@ impl Input {
         TryFrom::try_from(path).map_err(|e: Error| e.to_os_string(path))
     }
 
-    /// If input is a file, returns the size of the file, in bytes
-    /// otherwise if input is stdin returns none.
+    /// If input is a file, returns the size of the file, in bytes, or for a `data:` url the size
+    /// of its decoded contents, otherwise if input is stdin or is being decompressed returns none.
     ///
     /// # Examples
     ///
@@ -106,8 +220,13 @@ impl Input {
             InputStream::Stdin(_) => None,
             InputStream::Pipe(_) => None,
             InputStream::File(file) => file.metadata().ok().map(|x| x.len()),
+            InputStream::Data(data) => Some(data.get_ref().len() as u64),
             #[cfg(feature = "http")]
             InputStream::Http(http) => http.len(),
+            #[cfg(feature = "archive")]
+            InputStream::Archive(reader) => Some(reader.len()),
+            #[cfg(feature = "compression")]
+            InputStream::Compressed(_) => None,
         }
     }
 
@@ -154,8 +273,13 @@ impl Input {
             InputStream::Stdin(stdin) => Box::new(stdin.lock()),
             InputStream::Pipe(pipe) => Box::new(BufReader::new(pipe)),
             InputStream::File(file) => Box::new(BufReader::new(file)),
+            InputStream::Data(data) => Box::new(data),
             #[cfg(feature = "http")]
             InputStream::Http(http) => Box::new(BufReader::new(http)),
+            #[cfg(feature = "archive")]
+            InputStream::Archive(reader) => Box::new(BufReader::new(reader)),
+            #[cfg(feature = "compression")]
+            InputStream::Compressed(reader) => Box::new(BufReader::new(reader)),
         }
     }
 
@@ -166,7 +290,7 @@ impl Input {
 
     /// Returns true if this [`Input`] reads from stdin
     pub fn is_std(&self) -> bool {
-        matches!(self.stream, InputStream::Stdin(_))
+        self.path.is_std()
     }
 
     /// Returns true if this [`Input`] points to the local file system,
@@ -175,15 +299,38 @@ impl Input {
         self.path.is_local()
     }
 
-    /// Returns true if this is stdin and it is connected to a tty
+    /// Returns true if this is stdin or a file descriptor and it is connected to a tty
     pub fn is_tty(&self) -> bool {
-        self.is_std() && std::io::stdin().is_terminal()
+        if self.is_std() {
+            std::io::stdin().is_terminal()
+        } else {
+            self.path.is_tty()
+        }
     }
 
-    /// Returns `true` if this [`Input`] is a file,
-    /// and `false` if this [`Input`] is std out or a pipe
+    /// Details of the response if this [`Input`] is a url, such as the `Content-Type`
+    /// or the file name the server suggested
+    #[cfg(feature = "http")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+    pub fn http_metadata(&self) -> Option<&HttpMetadata> {
+        self.http_metadata.as_ref()
+    }
+
+    /// Details of a `data:` url such as its media type, or `None` if this [`Input`] is not one
+    pub fn data_metadata(&self) -> Option<&DataMetadata> {
+        self.path.data_metadata()
+    }
+
+    /// Returns `true` if this [`Input`] is a file, a `data:` url or a URL on a server that supports
+    /// range requests, and `false` if this [`Input`] is std out or a pipe
     pub fn can_seek(&self) -> bool {
-        matches!(self.stream, InputStream::File(_))
+        match &self.stream {
+            InputStream::File(_) => true,
+            InputStream::Data(_) => true,
+            #[cfg(feature = "http")]
+            InputStream::Http(http) => http.can_seek(),
+            _ => false,
+        }
     }
 }
 
@@ -191,13 +338,7 @@ impl_try_from!(Input);
 
 impl Read for Input {
     fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
-        match &mut self.stream {
-            InputStream::Stdin(stdin) => stdin.read(buf),
-            InputStream::Pipe(pipe) => pipe.read(buf),
-            InputStream::File(file) => file.read(buf),
-            #[cfg(feature = "http")]
-            InputStream::Http(reader) => reader.read(buf),
-        }
+        self.stream.read(buf)
     }
 }
 
@@ -206,6 +347,9 @@ impl Seek for Input {
         match &mut self.stream {
             InputStream::Pipe(pipe) => pipe.seek(pos),
             InputStream::File(file) => file.seek(pos),
+            InputStream::Data(data) => data.seek(pos),
+            #[cfg(feature = "http")]
+            InputStream::Http(http) => http.seek(pos),
             _ => Err(Error::seek_error().into()),
         }
     }
@@ -312,6 +456,11 @@ impl CachedInput {
         &self.path
     }
 
+    /// Details of a `data:` url such as its media type, or `None` if this [`CachedInput`] is not one
+    pub fn data_metadata(&self) -> Option<&DataMetadata> {
+        self.path.data_metadata()
+    }
+
     /// Resets the reader back to the start of the file
     pub fn reset(&mut self) {
         self.data.set_position(0)
@@ -373,40 +522,102 @@ impl_try_from!(CachedInput: Clone - Default);
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct InputPath {
     path: ClioPath,
+    /// the size the server gave when the url was checked
+    #[cfg(feature = "http")]
+    remote_len: Option<u64>,
 }
 
 impl InputPath {
     /// Constructs a new [`InputPath`] representing the path and checking that the file exists and is readable
     ///
+    /// For a member of an archive this checks that the archive contains the member,
+    /// and for a file descriptor that it is open.
+    ///
     /// note: even if this passes open may still fail if e.g. the file was delete in between
     pub fn new<S: TryInto<ClioPath>>(path: S) -> Result<Self>
     where
         crate::Error: From<<S as TryInto<ClioPath>>::Error>,
     {
-        let path: ClioPath = path.try_into()?.with_direction(InOut::In);
+        let path: ClioPath = path.try_into()?.for_direction(InOut::In)?;
         if path.is_local() {
             assert_exists(&path)?;
             assert_not_dir(&path)?;
             assert_readable(&path)?;
         };
-        Ok(InputPath { path })
+        #[cfg(unix)]
+        if let ClioPathEnum::Fd(fd) = &path.path {
+            fd.assert_open()?;
+        }
+        #[cfg(feature = "archive")]
+        if let ClioPathEnum::Archive(archive) = &path.path {
+            archive.assert_member_exists()?;
+        }
+        #[cfg(feature = "http")]
+        let remote_len = match &path.path {
+            // when offline this only looks in the cache so is always done
+            ClioPathEnum::Http(url) if path.check_remote || path.http_config().offline => {
+                crate::http::check_input(url.as_str(), &path.http_config())?
+            }
+            _ => None,
+        };
+        Ok(InputPath {
+            path,
+            #[cfg(feature = "http")]
+            remote_len,
+        })
     }
 
     /// Constructs a new [`InputPath`] to stdout ("-")
     pub fn std() -> Self {
         InputPath {
             path: ClioPath::std().with_direction(InOut::In),
+            #[cfg(feature = "http")]
+            remote_len: None,
         }
     }
 
+    /// The size of the input if it can be known without opening it
+    ///
+    /// That is the size of a local file, a file descriptor of a file or the decoded contents of a `data:` url, or of a url that was checked with
+    /// [`OsStrParser::check_remote`](crate::clapers::OsStrParser::check_remote)
+    /// if the server sent a `Content-Length`.
+    pub fn len(&self) -> Option<u64> {
+        match &self.path.path {
+            ClioPathEnum::Local(path) => path
+                .metadata()
+                .ok()
+                .filter(|metadata| metadata.is_file())
+                .map(|metadata| metadata.len()),
+            ClioPathEnum::Data(data) => Some(data.data.len() as u64),
+            #[cfg(unix)]
+            ClioPathEnum::Fd(fd) => fd
+                .metadata()
+                .ok()
+                .filter(|metadata| metadata.is_file())
+                .map(|metadata| metadata.len()),
+            #[cfg(feature = "http")]
+            ClioPathEnum::Http(_) => self.remote_len,
+            _ => None,
+        }
+    }
+
+    /// Returns a boolean saying if the input is empty, or None if its size is not known
+    pub fn is_empty(&self) -> Option<bool> {
+        self.len().map(|l| l == 0)
+    }
+
     /// Returns true if this [`InputPath`] is stdin
     pub fn is_std(&self) -> bool {
         self.path.is_std()
     }
 
-    /// Returns true if this is stdin and it is connected to a tty
+    /// Returns true if this is stdin or a file descriptor and it is connected to a tty
     pub fn is_tty(&self) -> bool {
-        self.is_std() && std::io::stdin().is_terminal()
+        if self.is_std() {
+            std::io::stdin().is_terminal()
+        } else {
+            self.path.is_tty()
+        }
     }
 
     /// Returns true if this [`InputPath`] is on the local file system,
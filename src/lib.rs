@@ -7,22 +7,62 @@
 #![cfg_attr(docsrs, feature(doc_cfg))]
 #![doc = include_str!("../README.md")]
 
+#[cfg(feature = "archive")]
+mod archive;
 #[cfg(feature = "clap-parse")]
 pub mod clapers;
+#[cfg(feature = "compression")]
+mod compression;
+mod data_uri;
 mod error;
+#[cfg(unix)]
+mod fd;
+mod file_url;
 #[cfg(feature = "http")]
 mod http;
 mod input;
 mod output;
+#[cfg(feature = "archive-tar")]
+mod output_dir;
 mod path;
 
+pub use crate::data_uri::DataMetadata;
 pub use crate::error::Error;
 pub use crate::error::Result;
+#[cfg(feature = "http")]
+#[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+pub use crate::http::download;
+#[cfg(feature = "http-curl")]
+#[cfg_attr(docsrs, doc(cfg(feature = "http-curl")))]
+pub use crate::http::CurlBackend;
+#[cfg(feature = "http")]
+#[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+pub use crate::http::HttpConfig;
+#[cfg(feature = "http")]
+#[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+pub use crate::http::HttpMetadata;
+#[cfg(feature = "http")]
+#[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+pub use crate::http::Method;
+#[cfg(feature = "http-reqwest")]
+#[cfg_attr(docsrs, doc(cfg(feature = "http-reqwest")))]
+pub use crate::http::ReqwestBackend;
+#[cfg(feature = "http")]
+#[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+pub use crate::http::UploadResponse;
+#[cfg(feature = "http-ureq")]
+#[cfg_attr(docsrs, doc(cfg(feature = "http-ureq")))]
+pub use crate::http::UreqBackend;
+#[cfg(feature = "http")]
+#[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+pub use crate::http::{set_http_backend, HttpBackend, HttpRequest, HttpResponse};
 pub use crate::input::CachedInput;
 pub use crate::input::Input;
 pub use crate::input::InputPath;
 pub use crate::output::Output;
 pub use crate::output::OutputPath;
+#[cfg(feature = "archive-tar")]
+pub use crate::output_dir::OutputDir;
 pub use crate::path::ClioPath;
 
 use std::ffi::OsStr;
@@ -290,8 +330,8 @@ mod tests {
 
     fn temp() -> TempDir {
         let tmp = tempdir().expect("could not make tmp dir");
-        create_dir(&tmp.path().join("dir")).expect("could not create dir");
-        write(&tmp.path().join("file"), "contents").expect("could not create dir");
+        create_dir(tmp.path().join("dir")).expect("could not create dir");
+        write(tmp.path().join("file"), "contents").expect("could not create dir");
         let ro = tmp.path().join("ro");
         write(&ro, "contents").expect("could not create ro");
         set_mode(&ro, 0o400).expect("could make ro read only");
@@ -337,7 +377,7 @@ mod tests {
                 f.read_to_string(&mut s)?;
                 Ok(s)
             });
-            let raw_w = write(&tmp_w.path().join(path), "junk");
+            let raw_w = write(tmp_w.path().join(path), "junk");
 
             let in_path_err = InputPath::new(&tmp_path);
             let open_err = Input::new(&tmp_path);
@@ -349,6 +389,58 @@ mod tests {
         }
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_std_streams() -> Result<()> {
+        for (name, dev) in [
+            ("/dev/stdin", "/dev/stdin"),
+            ("/dev/stdout", "/dev/stdout"),
+            ("/dev/stderr", "/dev/stderr"),
+        ] {
+            let path = ClioPath::new(name)?;
+            assert!(path.is_std(), "{name}");
+            assert!(!path.is_local(), "{name}");
+            assert_eq!(path.path(), Path::new(dev));
+            assert_eq!(path.to_string_lossy(), name);
+            // a ClioPath is displayed as its path, which names the same stream
+            let shown = ClioPath::new(path.to_string())?;
+            assert!(shown.is_std());
+            assert_eq!(shown.path(), path.path());
+        }
+
+        let stderr = Output::std_err();
+        assert!(stderr.is_std());
+        assert_eq!(stderr.path().path(), Path::new("/dev/stderr"));
+        assert_eq!(stderr.to_string(), "/dev/stderr");
+        // `-2` is only stderr if a parser asks for it
+        assert!(ClioPath::new("-2")?.is_local());
+        assert_eq!(
+            OutputPath::new("/dev/stderr")?.path().path(),
+            Path::new("/dev/stderr")
+        );
+
+        // the named streams only go one way, unlike `-`
+        assert_eq!(InputPath::new("-")?.path().path(), Path::new("/dev/stdin"));
+        assert_eq!(
+            OutputPath::new("-")?.path().path(),
+            Path::new("/dev/stdout")
+        );
+        assert!(InputPath::new("/dev/stdin")?.is_std());
+        assert!(InputPath::new("/dev/stderr").is_err());
+        assert!(OutputPath::new("/dev/stdin").is_err());
+        assert!(Input::new("/dev/stdout").is_err());
+        assert!(Output::new("/dev/stdin").is_err());
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    #[test]
+    fn test_fd_unsupported() {
+        let err = ClioPath::new("fd:3").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+        assert!(ClioPath::new("./fd:3").unwrap().is_local());
+    }
+
     #[cfg(any(target_os = "linux", target_os = "macos"))]
     fn comparable<E: std::fmt::Display, A>(
         a: std::result::Result<A, E>,
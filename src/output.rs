@@ -1,15 +1,16 @@
+#[cfg(feature = "compression")]
+use crate::compression::{Codec, Encoder};
 use crate::path::{ClioPathEnum, InOut};
 use crate::{
     assert_is_dir, assert_not_dir, assert_writeable, impl_try_from, is_fifo, ClioPath, Error,
     Result,
 };
 
-use is_terminal::IsTerminal;
 use std::convert::TryFrom;
 use std::ffi::OsStr;
 use std::fmt::{self, Debug, Display};
 use std::fs::{File, OpenOptions};
-use std::io::{self, Result as IoResult, Seek, Stderr, Stdout, Write};
+use std::io::{self, Read, Result as IoResult, Seek, SeekFrom, Stderr, Stdout, Write};
 use std::path::Path;
 use tempfile::NamedTempFile;
 
@@ -29,10 +30,20 @@ enum OutputStream {
     #[cfg_attr(docsrs, doc(cfg(feature = "http")))]
     /// a writer that will upload the body the the HTTP server
     Http(Box<HttpWriter>),
+    #[cfg(feature = "compression")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
+    /// one of the other streams wrapped in an encoder that compresses it
+    Compressed(Box<Encoder<OutputStream>>),
+    #[cfg(feature = "archive-tar")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "archive-tar")))]
+    /// a file in an [`OutputDir`](crate::OutputDir) that will be added to a tar stream when finished
+    TarEntry(Box<TarEntry>),
 }
 
 #[cfg(feature = "http")]
-use crate::http::HttpWriter;
+use crate::http::{HttpWriter, Upload, UploadResponse};
+#[cfg(feature = "archive-tar")]
+use crate::output_dir::TarEntry;
 /// A struct that represents a command line output stream,
 /// either [`Stdout`] or a [`File`] along with it's path
 ///
@@ -93,9 +104,36 @@ pub struct OutputPath {
 
 impl OutputStream {
     /// Constructs a new output either by opening/creating the file or for '-' returning stdout
+    ///
+    /// If the extension is for a compression format the stream will be wrapped in an encoder
     fn new(path: &ClioPath, size: Option<u64>) -> Result<Self> {
+        #[cfg(feature = "compression")]
+        if !path.raw && Codec::from_extension(path.extension()).is_some() {
+            // the size is of the uncompressed data so is no use to the file
+            return OutputStream::open(path, None)?.compress(path);
+        }
+        OutputStream::open(path, size)
+    }
+
+    /// Wraps the stream in an [`Encoder`] if the extension says it should be compressed
+    #[cfg(feature = "compression")]
+    fn compress(self, path: &ClioPath) -> Result<Self> {
+        if !path.raw {
+            if let Some(codec) = Codec::from_extension(path.extension()) {
+                let encoder = Encoder::new(codec, self, path.compression_level)?;
+                return Ok(OutputStream::Compressed(Box::new(encoder)));
+            }
+        }
+        Ok(self)
+    }
+
+    fn open(path: &ClioPath, size: Option<u64>) -> Result<Self> {
         Ok(match &path.path {
-            ClioPathEnum::Std(_) => OutputStream::Stdout(io::stdout()),
+            ClioPathEnum::Std(std) => match std.stream(InOut::Out) {
+                InOut::Out => OutputStream::Stdout(io::stdout()),
+                InOut::Err => OutputStream::Stderr(io::stderr()),
+                InOut::In => return Err(Error::std_direction_error()),
+            },
             ClioPathEnum::Local(local_path) => {
                 if path.atomic && !path.is_fifo() {
                     assert_not_dir(path)?;
@@ -121,11 +159,96 @@ impl OutputStream {
                 }
             }
             #[cfg(feature = "http")]
-            ClioPathEnum::Http(url) => {
-                OutputStream::Http(Box::new(HttpWriter::new(url.as_str(), size)?))
+            ClioPathEnum::Http(url) => OutputStream::Http(Box::new(HttpWriter::new(
+                Upload::new(url, path, Vec::new()),
+                size,
+            )?)),
+            ClioPathEnum::Data(_) => return Err(Error::data_uri_write_error()),
+            #[cfg(unix)]
+            ClioPathEnum::Fd(fd) => {
+                let file = fd.open_write()?;
+                if is_fifo(&file.metadata()?) {
+                    OutputStream::Pipe(file)
+                } else {
+                    OutputStream::File(file)
+                }
             }
+            #[cfg(feature = "archive")]
+            ClioPathEnum::Archive(_) => return Err(Error::archive_write_error()),
         })
     }
+
+    /// Flushes and closes the stream, finalising compressed streams and atomic files
+    fn finish(self, path: &ClioPath) -> Result<()> {
+        match self {
+            OutputStream::Stdout(_) => Ok(()),
+            OutputStream::Stderr(_) => Ok(()),
+            OutputStream::Pipe(_) => Ok(()),
+            OutputStream::File(file) => Ok(file.sync_data()?),
+            OutputStream::AtomicFile(tmp) => {
+                tmp.persist(path.path())?;
+                Ok(())
+            }
+            #[cfg(feature = "http")]
+            OutputStream::Http(http) => Ok(http.finish()?),
+            #[cfg(feature = "compression")]
+            OutputStream::Compressed(encoder) => {
+                let mut stream = encoder.finish()?;
+                stream.flush()?;
+                stream.finish(path)
+            }
+            #[cfg(feature = "archive-tar")]
+            OutputStream::TarEntry(entry) => entry.finish(),
+        }
+    }
+
+    /// Finishes the stream, returning the response to the upload if it was a url
+    #[cfg(feature = "http")]
+    fn finish_with_response(self, path: &ClioPath) -> Result<Option<UploadResponse>> {
+        match self {
+            OutputStream::Http(http) => http.finish_with_response(),
+            #[cfg(feature = "compression")]
+            OutputStream::Compressed(encoder) => {
+                let mut stream = encoder.finish()?;
+                stream.flush()?;
+                stream.finish_with_response(path)
+            }
+            stream => stream.finish(path).map(|()| None),
+        }
+    }
+}
+
+impl Write for OutputStream {
+    fn flush(&mut self) -> IoResult<()> {
+        match self {
+            OutputStream::Stdout(stdout) => stdout.flush(),
+            OutputStream::Stderr(stderr) => stderr.flush(),
+            OutputStream::Pipe(pipe) => pipe.flush(),
+            OutputStream::File(file) => file.flush(),
+            OutputStream::AtomicFile(file) => file.flush(),
+            #[cfg(feature = "http")]
+            OutputStream::Http(http) => http.flush(),
+            #[cfg(feature = "compression")]
+            OutputStream::Compressed(encoder) => encoder.flush(),
+            #[cfg(feature = "archive-tar")]
+            OutputStream::TarEntry(entry) => entry.flush(),
+        }
+    }
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        match self {
+            OutputStream::Stdout(stdout) => stdout.write(buf),
+            OutputStream::Stderr(stderr) => stderr.write(buf),
+            OutputStream::Pipe(pipe) => pipe.write(buf),
+            OutputStream::File(file) => file.write(buf),
+            OutputStream::AtomicFile(file) => file.write(buf),
+            #[cfg(feature = "http")]
+            OutputStream::Http(http) => http.write(buf),
+            #[cfg(feature = "compression")]
+            OutputStream::Compressed(encoder) => encoder.write(buf),
+            #[cfg(feature = "archive-tar")]
+            OutputStream::TarEntry(entry) => entry.write(buf),
+        }
+    }
 }
 
 impl Output {
@@ -145,6 +268,61 @@ impl Output {
         })
     }
 
+    /// Constructs a new output for a url that is uploaded with extra headers, such as `If-Match`
+    #[cfg(feature = "http")]
+    fn with_headers(
+        path: ClioPath,
+        headers: Vec<(String, String)>,
+        size: Option<u64>,
+    ) -> Result<Self> {
+        let upload = match &path.path {
+            ClioPathEnum::Http(url) => Upload::new(url, &path, headers),
+            _ => return Err(Error::conditional_write_error()),
+        };
+        #[cfg(feature = "compression")]
+        // the size is of the uncompressed data so is no use to the upload
+        let size = size.filter(|_| path.raw || Codec::from_extension(path.extension()).is_none());
+        let stream = OutputStream::Http(Box::new(HttpWriter::new(upload, size)?));
+        #[cfg(feature = "compression")]
+        let stream = stream.compress(&path)?;
+        Ok(Output { path, stream })
+    }
+
+    /// Constructs a new output for a file that will be added to a tar stream when it is finished
+    #[cfg(feature = "archive-tar")]
+    pub(crate) fn tar_entry(path: ClioPath, entry: TarEntry) -> Result<Self> {
+        let stream = OutputStream::TarEntry(Box::new(entry));
+        #[cfg(feature = "compression")]
+        let stream = stream.compress(&path)?;
+        Ok(Output { path, stream })
+    }
+
+    /// Constructs an atomic output that appends to `partial` rather than a new temporary file,
+    /// so that a download can carry on from where an earlier attempt stopped
+    ///
+    /// Like any other atomic output `partial` is renamed to the path by [`finish`](Output::finish)
+    /// and deleted if the output is dropped, unless it is kept with [`keep_partial`](Output::keep_partial).
+    #[cfg(feature = "http")]
+    pub(crate) fn resume_atomic(path: ClioPath, partial: &Path) -> Result<Self> {
+        assert_not_dir(&path)?;
+        let file = OpenOptions::new().append(true).create(true).open(partial)?;
+        let tmp = NamedTempFile::from_parts(file, tempfile::TempPath::from_path(partial));
+        Ok(Output {
+            path,
+            stream: OutputStream::AtomicFile(tmp),
+        })
+    }
+
+    /// Leaves the temporary file of an unfinished atomic output in place, rather than deleting it
+    #[cfg(feature = "http")]
+    pub(crate) fn keep_partial(mut self) -> Result<()> {
+        self.flush()?;
+        if let OutputStream::AtomicFile(tmp) = self.stream {
+            tmp.keep().map_err(|err| err.error)?;
+        }
+        Ok(())
+    }
+
     /// Constructs a new output for stdout
     pub fn std() -> Self {
         Output {
@@ -153,22 +331,29 @@ impl Output {
         }
     }
 
-    /// Constructs a new output for stdout
+    /// Constructs a new output for stderr
+    ///
+    /// It is shown as `/dev/stderr`, which is only parsed back as stderr on Unix, on other
+    /// platforms it names a file. With the `clap-parse` feature an `OsStrParser` given a
+    /// `stderr_token` such as `-2` accepts that token for stderr on every platform.
     pub fn std_err() -> Self {
         Output {
-            path: ClioPath::std().with_direction(InOut::Out),
+            path: ClioPath::std_err(),
             stream: OutputStream::Stderr(io::stderr()),
         }
     }
 
-    /// Returns true if this Output is stout
+    /// Returns true if this Output is stdout or stderr
     pub fn is_std(&self) -> bool {
-        matches!(self.stream, OutputStream::Stdout(_))
+        self.path.is_std()
     }
 
-    /// Returns true if this is stdout and it is connected to a tty
+    /// Returns true if this is stdout, stderr or a file descriptor and it is connected to a tty
     pub fn is_tty(&self) -> bool {
-        self.is_std() && std::io::stdout().is_terminal()
+        match self.path.std_stream(InOut::Out) {
+            Some(stream) => stream.is_terminal(),
+            None => self.path.is_tty(),
+        }
     }
 
     /// Returns true if this Output is on the local file system,
@@ -189,20 +374,31 @@ impl Output {
     /// Syncs the file to disk or closes any HTTP connections and returns any errors
     /// or on the file if a regular file
     /// For atomic files this must be called to perform the final atomic swap
+    /// and for compressed outputs it must be called to write the trailer
     pub fn finish(mut self) -> Result<()> {
         self.flush()?;
-        match self.stream {
-            OutputStream::Stdout(_) => Ok(()),
-            OutputStream::Stderr(_) => Ok(()),
-            OutputStream::Pipe(_) => Ok(()),
-            OutputStream::File(file) => Ok(file.sync_data()?),
-            OutputStream::AtomicFile(tmp) => {
-                tmp.persist(self.path.path())?;
-                Ok(())
-            }
-            #[cfg(feature = "http")]
-            OutputStream::Http(http) => Ok(http.finish()?),
-        }
+        self.stream.finish(&self.path)
+    }
+
+    /// Like [`finish`](Output::finish) but also returns the server's response when the output
+    /// is a url, so the `ETag`, `Location` or version id of the upload can be read,
+    /// for any other output it returns `None`
+    ///
+    /// ```no_run
+    /// use std::io::Write;
+    ///
+    /// let mut output = clio::Output::new("https://example.com/upload/report.csv")?;
+    /// output.write_all(b"a,b,c")?;
+    /// if let Some(response) = output.finish_with_response()? {
+    ///     eprintln!("uploaded {:?}", response.etag());
+    /// }
+    /// # Ok::<(), clio::Error>(())
+    /// ```
+    #[cfg(feature = "http")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+    pub fn finish_with_response(mut self) -> Result<Option<UploadResponse>> {
+        self.flush()?;
+        self.stream.finish_with_response(&self.path)
     }
 
     /// If the output is std out [locks](std::io::Stdout::lock) it.
@@ -227,6 +423,10 @@ impl Output {
             OutputStream::AtomicFile(file) => Box::new(file),
             #[cfg(feature = "http")]
             OutputStream::Http(http) => Box::new(http),
+            #[cfg(feature = "compression")]
+            OutputStream::Compressed(encoder) => Box::new(encoder),
+            #[cfg(feature = "archive-tar")]
+            OutputStream::TarEntry(entry) => Box::new(entry),
         }
     }
 
@@ -259,26 +459,10 @@ impl_try_from!(Output);
 
 impl Write for Output {
     fn flush(&mut self) -> IoResult<()> {
-        match &mut self.stream {
-            OutputStream::Stdout(stdout) => stdout.flush(),
-            OutputStream::Stderr(stderr) => stderr.flush(),
-            OutputStream::Pipe(pipe) => pipe.flush(),
-            OutputStream::File(file) => file.flush(),
-            OutputStream::AtomicFile(file) => file.flush(),
-            #[cfg(feature = "http")]
-            OutputStream::Http(http) => http.flush(),
-        }
+        self.stream.flush()
     }
     fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
-        match &mut self.stream {
-            OutputStream::Stdout(stdout) => stdout.write(buf),
-            OutputStream::Stderr(stderr) => stderr.write(buf),
-            OutputStream::Pipe(pipe) => pipe.write(buf),
-            OutputStream::File(file) => file.write(buf),
-            OutputStream::AtomicFile(file) => file.write(buf),
-            #[cfg(feature = "http")]
-            OutputStream::Http(http) => http.write(buf),
-        }
+        self.stream.write(buf)
     }
 }
 
@@ -300,7 +484,7 @@ impl OutputPath {
     where
         crate::Error: From<<S as TryInto<ClioPath>>::Error>,
     {
-        let path: ClioPath = path.try_into()?.with_direction(InOut::Out);
+        let path: ClioPath = path.try_into()?.for_direction(InOut::Out)?;
         if path.is_local() {
             if path.exists() && !path.atomic {
                 assert_not_dir(&path)?;
@@ -319,6 +503,23 @@ impl OutputPath {
                 }
             }
         }
+        if let ClioPathEnum::Data(_) = &path.path {
+            return Err(Error::data_uri_write_error());
+        }
+        #[cfg(unix)]
+        if let ClioPathEnum::Fd(fd) = &path.path {
+            fd.assert_open()?;
+        }
+        #[cfg(feature = "archive")]
+        if let ClioPathEnum::Archive(_) = &path.path {
+            return Err(Error::archive_write_error());
+        }
+        #[cfg(feature = "http")]
+        if let ClioPathEnum::Http(url) = &path.path {
+            if path.check_remote {
+                crate::http::check_output(url.as_str(), path.http_method, &path.http_config())?;
+            }
+        }
         Ok(OutputPath { path })
     }
 
@@ -344,19 +545,107 @@ impl OutputPath {
         self.maybe_with_len(None)
     }
 
+    /// Create an [`Output`] that only replaces the file at the url if it has not changed since it was read,
+    /// by sending its `ETag` in an `If-Match` header
+    ///
+    /// The `ETag` can be taken from the [`HttpMetadata`](crate::HttpMetadata::etag) of the [`Input`](crate::Input) it was read from.
+    /// If the file was changed in the meantime the server responds with `412 Precondition Failed`,
+    /// which is an [`Error::Http`] with the [kind](Error::kind) [`AlreadyExists`](std::io::ErrorKind::AlreadyExists),
+    /// either from this or from [`Output::finish`] depending on when the server checked.
+    ///
+    /// It is an error if the path is not a url.
+    ///
+    /// ```no_run
+    /// use clio::{Input, OutputPath};
+    /// use std::io::{ErrorKind, Read, Write};
+    ///
+    /// let url = "https://example.com/config.toml";
+    /// let mut input = Input::new(url)?;
+    /// let etag = input.http_metadata().and_then(|m| m.etag()).unwrap().to_owned();
+    /// let mut config = String::new();
+    /// input.read_to_string(&mut config)?;
+    ///
+    /// let mut output = OutputPath::new(url)?.create_if_match(&etag)?;
+    /// output.write_all(config.replace("old", "new").as_bytes())?;
+    /// match output.finish() {
+    ///     Err(err) if err.kind() == ErrorKind::AlreadyExists => eprintln!("changed by someone else"),
+    ///     result => result?,
+    /// }
+    /// # Ok::<(), clio::Error>(())
+    /// ```
+    #[cfg(feature = "http")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+    pub fn create_if_match(self, etag: &str) -> Result<Output> {
+        Output::with_headers(self.path, if_match(etag), None)
+    }
+
+    /// Like [`create_if_match`](Self::create_if_match), sending `size` as the `content-length` of the upload
+    #[cfg(feature = "http")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+    pub fn create_if_match_with_len(self, etag: &str, size: u64) -> Result<Output> {
+        Output::with_headers(self.path, if_match(etag), Some(size))
+    }
+
+    /// Create an [`Output`] that fails rather than replace a file that already exists at the url,
+    /// by sending `If-None-Match: *`
+    ///
+    /// If the file exists the server responds with `412 Precondition Failed`, which is an
+    /// [`Error::Http`] with the [kind](Error::kind) [`AlreadyExists`](std::io::ErrorKind::AlreadyExists),
+    /// either from this or from [`Output::finish`] depending on when the server checked.
+    ///
+    /// It is an error if the path is not a url.
+    #[cfg(feature = "http")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+    pub fn create_new(self) -> Result<Output> {
+        Output::with_headers(self.path, if_none_match(), None)
+    }
+
+    /// Like [`create_new`](Self::create_new), sending `size` as the `content-length` of the upload
+    #[cfg(feature = "http")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+    pub fn create_new_with_len(self, size: u64) -> Result<Output> {
+        Output::with_headers(self.path, if_none_match(), Some(size))
+    }
+
+    /// Copies the rest of `input` to the output and finishes it, returning the number of bytes copied.
+    ///
+    /// The input must be able to seek, e.g. an [`Input`](crate::Input) of a file or a
+    /// [`CachedInput`](crate::CachedInput), so that its length can be set before writing,
+    /// and so that an HTTP upload can be [retried](crate::HttpConfig::retries) by sending it again.
+    pub fn copy_from<R: Read + Seek>(self, input: &mut R) -> Result<u64> {
+        #[cfg(feature = "http")]
+        if let ClioPathEnum::Http(url) = &self.path.path {
+            #[cfg(feature = "compression")]
+            let compressed =
+                !self.path.raw && Codec::from_extension(self.path.extension()).is_some();
+            #[cfg(not(feature = "compression"))]
+            let compressed = false;
+            if !compressed {
+                return Upload::new(url, &self.path, Vec::new()).send_seekable(input);
+            }
+        }
+        let start = input.stream_position()?;
+        let size = input.seek(SeekFrom::End(0))? - start;
+        input.seek(SeekFrom::Start(start))?;
+        let mut output = self.create_with_len(size)?;
+        let copied = io::copy(input, &mut output)?;
+        output.finish()?;
+        Ok(copied)
+    }
+
     /// The original path represented by this [`OutputPath`]
     pub fn path(&self) -> &ClioPath {
         &self.path
     }
 
-    /// Returns true if this [`Output`] is stdout
+    /// Returns true if this [`Output`] is stdout or stderr
     pub fn is_std(&self) -> bool {
         self.path.is_std()
     }
 
-    /// Returns true if this is stdout and it is connected to a tty
+    /// Returns true if this is stdout, stderr or a file descriptor and it is connected to a tty
     pub fn is_tty(&self) -> bool {
-        self.is_std() && std::io::stdout().is_terminal()
+        self.path.is_tty()
     }
 
     /// Returns true if this [`Output`] is on the local file system,
@@ -395,3 +684,13 @@ fn open_w(path: &Path) -> io::Result<File> {
         .truncate(false)
         .open(path)
 }
+
+#[cfg(feature = "http")]
+fn if_match(etag: &str) -> Vec<(String, String)> {
+    vec![("If-Match".to_owned(), etag.to_owned())]
+}
+
+#[cfg(feature = "http")]
+fn if_none_match() -> Vec<(String, String)> {
+    vec![("If-None-Match".to_owned(), "*".to_owned())]
+}
@@ -0,0 +1,359 @@
+use crate::path::{ClioPathEnum, InOut};
+use crate::{assert_is_dir, assert_writeable, impl_try_from, ClioPath, Error, Output, Result};
+
+use is_terminal::IsTerminal;
+use std::convert::TryFrom;
+use std::ffi::OsStr;
+use std::fmt::{self, Debug, Display};
+use std::fs::{self, File};
+use std::io::{self, Result as IoResult, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+type TarBuilder = Arc<Mutex<tar::Builder<Box<dyn Write + Send>>>>;
+
+enum DirTarget {
+    /// files are written straight into the directory
+    Dir(PathBuf),
+    /// files are written into a temp dir that is renamed over the directory when finished
+    AtomicDir(TempDir),
+    /// files are written as entries of a tar stream on stdout
+    Tar(TarBuilder),
+}
+
+/// A struct that represents a command line output directory,
+/// either a real directory or for `-` a tar stream written to stdout
+///
+/// Each file is created with [`create_file`](OutputDir::create_file) which
+/// returns an [`Output`] so it can be written the same way as any other output.
+///
+/// Each [`Output`] must be [finished](Output::finish) before the [`OutputDir`]
+/// is [finished](OutputDir::finish), when writing to a tar stream any file that
+/// was not finished will be left out of the stream.
+///
+/// If the path was made [atomic](crate::clapers::OsStrParser::atomic) the files are written to a temporary directory
+/// next to the destination that replaces it when [`finish`](OutputDir::finish) is called.
+///
+/// It is designed to be used with the [`clap` crate](https://docs.rs/clap/latest) when taking a directory name as an
+/// argument to CLI app
+/// ```
+/// # #[cfg(feature="clap-parse")]{
+/// use clap::Parser;
+/// use clio::OutputDir;
+///
+/// #[derive(Parser)]
+/// struct Opt {
+///     /// directory to write the report to, use '-' for a tar file on stdout
+///     #[clap(value_parser)]
+///     report_dir: OutputDir,
+/// }
+/// # }
+/// ```
+pub struct OutputDir {
+    path: ClioPath,
+    target: DirTarget,
+}
+
+impl OutputDir {
+    /// Constructs a new output directory, creating it if it does not exist,
+    /// or for '-' returning a tar stream on stdout
+    pub fn new<S: TryInto<ClioPath>>(path: S) -> Result<Self>
+    where
+        crate::Error: From<<S as TryInto<ClioPath>>::Error>,
+    {
+        let path: ClioPath = path.try_into()?.for_direction(InOut::Out)?;
+        let target = match &path.path {
+            ClioPathEnum::Std(std) if std.stream(InOut::Out) == InOut::Out => {
+                DirTarget::Tar(stdout_tar())
+            }
+            ClioPathEnum::Local(local) if path.atomic => {
+                if local.exists() {
+                    assert_is_dir(local)?;
+                }
+                let parent = path.safe_parent().ok_or_else(Error::not_found_error)?;
+                assert_is_dir(parent)?;
+                assert_writeable(parent)?;
+                DirTarget::AtomicDir(
+                    tempfile::Builder::new()
+                        .prefix(".atomicwrite")
+                        .tempdir_in(parent)?,
+                )
+            }
+            ClioPathEnum::Local(local) => {
+                if local.exists() {
+                    assert_is_dir(local)?;
+                } else {
+                    fs::create_dir_all(local)?;
+                }
+                assert_writeable(local)?;
+                DirTarget::Dir(local.clone())
+            }
+            #[allow(unreachable_patterns)]
+            _ => return Err(Error::output_dir_error()),
+        };
+        Ok(OutputDir { path, target })
+    }
+
+    /// Constructs a new [`OutputDir`] of `"-"` writing a tar stream to stdout
+    pub fn std() -> Self {
+        OutputDir {
+            path: ClioPath::std().with_direction(InOut::Out),
+            target: DirTarget::Tar(stdout_tar()),
+        }
+    }
+
+    /// Creates a file in the directory, `name` must be a relative path
+    /// and can include sub directories which will be created if needed.
+    ///
+    /// Files with a compression extension are compressed, just like a normal [`Output`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), clio::Error> {
+    /// use std::io::Write;
+    ///
+    /// let dir = clio::OutputDir::new("-")?;
+    /// let mut file = dir.create_file("sub/name.txt")?;
+    /// writeln!(file, "hello world")?;
+    /// file.finish()?;
+    /// dir.finish()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn create_file<P: AsRef<Path>>(&self, name: P) -> Result<Output> {
+        let name = name.as_ref();
+        if name.as_os_str().is_empty()
+            || !name.components().all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "file names in an output directory must be relative and not contain '..'",
+            )));
+        }
+        let dir = match &self.target {
+            DirTarget::Dir(dir) => dir.as_path(),
+            DirTarget::AtomicDir(tmp) => tmp.path(),
+            DirTarget::Tar(builder) => {
+                let entry = TarEntry {
+                    name: name.to_owned(),
+                    buffer: tempfile::tempfile()?,
+                    builder: builder.clone(),
+                };
+                return Output::tar_entry(self.file_path(name), entry);
+            }
+        };
+        let file = dir.join(name);
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Output::new(self.file_path(&file))
+    }
+
+    /// A copy of this directory's path pointing at the file, keeping the per argument options
+    fn file_path(&self, file: &Path) -> ClioPath {
+        ClioPath {
+            path: ClioPathEnum::Local(file.to_owned()),
+            atomic: false,
+            ..self.path.clone()
+        }
+    }
+
+    /// Finishes writing the directory
+    ///
+    /// For a tar stream this writes the end of archive marker,
+    /// for an atomic directory it replaces the destination with the temporary directory.
+    pub fn finish(self) -> Result<()> {
+        match self.target {
+            DirTarget::Dir(_) => Ok(()),
+            DirTarget::AtomicDir(tmp) => {
+                let dest = self.path.path();
+                let dir = tmp.into_path();
+                if dest.exists() {
+                    // a non empty directory can not be renamed over so move the old one out of the way first
+                    let old = tempfile::Builder::new()
+                        .prefix(".atomicwrite")
+                        .tempdir_in(dir.parent().unwrap_or(&dir))?;
+                    fs::rename(dest, old.path().join("old"))?;
+                    fs::rename(&dir, dest)?;
+                    old.close()?;
+                } else {
+                    fs::rename(&dir, dest)?;
+                }
+                Ok(())
+            }
+            DirTarget::Tar(builder) => {
+                let mut builder = builder.lock().unwrap_or_else(|e| e.into_inner());
+                builder.finish()?;
+                builder.get_mut().flush()?;
+                Ok(())
+            }
+        }
+    }
+
+    /// The original path used to create this [`OutputDir`]
+    pub fn path(&self) -> &ClioPath {
+        &self.path
+    }
+
+    /// Returns true if this [`OutputDir`] is a tar stream on stdout
+    pub fn is_std(&self) -> bool {
+        self.path.is_std()
+    }
+
+    /// Returns true if this is stdout and it is connected to a tty
+    pub fn is_tty(&self) -> bool {
+        self.is_std() && std::io::stdout().is_terminal()
+    }
+
+    /// Returns true if this [`OutputDir`] is on the local file system,
+    /// as opposed to a tar stream on stdout
+    pub fn is_local(&self) -> bool {
+        self.path.is_local()
+    }
+}
+
+impl_try_from!(OutputDir);
+
+fn stdout_tar() -> TarBuilder {
+    Arc::new(Mutex::new(tar::Builder::new(Box::new(io::stdout()))))
+}
+
+impl Debug for OutputDir {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let target = match &self.target {
+            DirTarget::Dir(_) => "Dir",
+            DirTarget::AtomicDir(_) => "AtomicDir",
+            DirTarget::Tar(_) => "Tar",
+        };
+        f.debug_struct("OutputDir")
+            .field("path", &self.path)
+            .field("target", &target)
+            .finish()
+    }
+}
+
+/// A file being written to a temporary buffer until it is finished and added to the tar stream
+pub(crate) struct TarEntry {
+    name: PathBuf,
+    buffer: File,
+    builder: TarBuilder,
+}
+
+impl TarEntry {
+    pub(crate) fn finish(mut self) -> Result<()> {
+        let size = self.buffer.seek(SeekFrom::End(0))?;
+        self.buffer.seek(SeekFrom::Start(0))?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(size);
+        header.set_mode(0o644);
+        header.set_mtime(
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+        );
+        let mut builder = self.builder.lock().unwrap_or_else(|e| e.into_inner());
+        builder.append_data(&mut header, &self.name, &mut self.buffer)?;
+        Ok(())
+    }
+}
+
+impl Write for TarEntry {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        self.buffer.write(buf)
+    }
+    fn flush(&mut self) -> IoResult<()> {
+        self.buffer.flush()
+    }
+}
+
+impl Debug for TarEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TarEntry")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_create_file_in_dir() -> Result<()> {
+        let tmp = tempdir()?;
+        let dir = OutputDir::new(&tmp.path().join("out"))?;
+        let mut file = dir.create_file("sub/name.txt")?;
+        writeln!(file, "hello")?;
+        file.finish()?;
+        dir.finish()?;
+        let contents = fs::read_to_string(tmp.path().join("out/sub/name.txt"))?;
+        assert_eq!(contents, "hello\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_escaping_names() -> Result<()> {
+        let tmp = tempdir()?;
+        let dir = OutputDir::new(tmp.path())?;
+        for name in ["../x.txt", "/etc/x.txt", ""] {
+            let err = dir.create_file(name).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{name}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_atomic_dir_replaces_on_finish() -> Result<()> {
+        let tmp = tempdir()?;
+        let out = tmp.path().join("out");
+        fs::create_dir(&out)?;
+        fs::write(out.join("stale.txt"), "old")?;
+
+        let mut path = ClioPath::new(&out)?;
+        path.atomic = true;
+        let dir = OutputDir::new(path)?;
+        let mut file = dir.create_file("new.txt")?;
+        file.write_all(b"new")?;
+        file.finish()?;
+        assert!(out.join("stale.txt").exists());
+        assert!(!out.join("new.txt").exists());
+
+        dir.finish()?;
+        assert!(!out.join("stale.txt").exists());
+        assert_eq!(fs::read_to_string(out.join("new.txt"))?, "new");
+        assert_eq!(fs::read_dir(tmp.path())?.count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tar_stream() -> Result<()> {
+        let mut tar_file = tempfile::tempfile()?;
+        let writer: Box<dyn Write + Send> = Box::new(tar_file.try_clone()?);
+        let dir = OutputDir {
+            path: ClioPath::std().with_direction(InOut::Out),
+            target: DirTarget::Tar(Arc::new(Mutex::new(tar::Builder::new(writer)))),
+        };
+        let mut file = dir.create_file("sub/name.txt")?;
+        file.write_all(b"hello")?;
+        file.finish()?;
+        let mut unfinished = dir.create_file("lost.txt")?;
+        unfinished.write_all(b"lost")?;
+        dir.finish()?;
+
+        tar_file.seek(SeekFrom::Start(0))?;
+        let mut archive = tar::Archive::new(tar_file);
+        let mut entries = archive.entries()?;
+        let mut entry = entries.next().unwrap()?;
+        assert_eq!(entry.path()?, Path::new("sub/name.txt"));
+        let mut contents = String::new();
+        io::Read::read_to_string(&mut entry, &mut contents)?;
+        assert_eq!(contents, "hello");
+        drop(entry);
+        assert!(entries.next().is_none());
+        Ok(())
+    }
+}
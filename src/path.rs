@@ -1,4 +1,9 @@
-use crate::{impl_try_from, is_fifo, CachedInput, Input, Output, Result};
+use crate::data_uri::DataUri;
+#[cfg(unix)]
+use crate::fd::FdPath;
+use crate::{
+    file_url, impl_try_from, is_fifo, CachedInput, DataMetadata, Error, Input, Output, Result,
+};
 
 use is_terminal::IsTerminal;
 use std::borrow::Cow;
@@ -9,9 +14,12 @@ use std::ops::Deref;
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
+#[cfg(feature = "archive")]
+use crate::archive::ArchivePath;
 #[cfg(feature = "http")]
 use {
-    crate::http::{is_http, try_to_url},
+    crate::http::{is_http, network_disabled, try_to_url, HttpConfig, Method},
+    std::sync::Arc,
     url::Url,
 };
 /// A builder for [Input](crate::Input) and [Output](crate::Output).
@@ -60,74 +68,252 @@ use {
 pub struct ClioPath {
     pub(crate) path: ClioPathEnum,
     pub(crate) atomic: bool,
+    pub(crate) raw: bool,
+    pub(crate) compression_level: Option<i32>,
+    /// the `file://` url the path was parsed from
+    pub(crate) file_url: Option<OsString>,
+    /// show the path as the `file://` url it was parsed from
+    pub(crate) keep_file_url: bool,
+    #[cfg(feature = "http")]
+    pub(crate) http_config: Option<Arc<HttpConfig>>,
+    #[cfg(feature = "http")]
+    pub(crate) check_remote: bool,
+    #[cfg(feature = "http")]
+    pub(crate) http_method: Method,
+    /// the name of the form field to upload the file as
+    #[cfg(feature = "http")]
+    pub(crate) multipart: Option<String>,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum InOut {
     In,
     Out,
+    Err,
+}
+
+impl InOut {
+    pub(crate) fn is_terminal(self) -> bool {
+        match self {
+            InOut::In => std::io::stdin().is_terminal(),
+            InOut::Out => std::io::stdout().is_terminal(),
+            InOut::Err => std::io::stderr().is_terminal(),
+        }
+    }
+}
+
+/// The names that always refer to one of the std streams
+#[cfg(unix)]
+const STD_NAMES: [(&str, InOut); 3] = [
+    ("/dev/stdin", InOut::In),
+    ("/dev/stdout", InOut::Out),
+    ("/dev/stderr", InOut::Err),
+];
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum StdStream {
+    /// the std token, `'-'` unless changed with `OsStrParser::std_token`, which is stdin or
+    /// stdout once it is known which way it is used
+    Token(&'static str, Option<InOut>),
+    /// a stream given by one of the `STD_NAMES` e.g. `/dev/stderr`, or the stderr token if there is one
+    Named(&'static str, InOut),
+}
+
+impl StdStream {
+    /// The stream this is, or `default` for the std token if it is not known yet
+    pub(crate) fn stream(self, default: InOut) -> InOut {
+        match self {
+            StdStream::Token(_, stream) => stream.unwrap_or(default),
+            StdStream::Named(_, stream) => stream,
+        }
+    }
+
+    /// The argument the stream was given as
+    fn as_str(self) -> &'static str {
+        match self {
+            StdStream::Token(token, _) | StdStream::Named(token, _) => token,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub(crate) enum ClioPathEnum {
-    /// stdin or stdout from a cli arg of `'-'`
-    Std(Option<InOut>),
+    /// stdin, stdout or stderr from the std token of `'-'`, or named e.g. `/dev/stderr`
+    Std(StdStream),
     /// a path to local file which may or may not exist
     Local(PathBuf),
+    /// the inline contents of a `data:` url e.g. `data:text/plain;base64,SGVsbG8=`
+    Data(DataUri),
+    #[cfg(unix)]
+    /// a file descriptor inherited from the parent process e.g. `fd:3` or `/dev/fd/3`
+    Fd(FdPath),
     #[cfg(feature = "http")]
     /// a http URL to a file on the web
     Http(Url),
+    #[cfg(feature = "archive")]
+    /// a member of a local tar or zip archive e.g. `bundle.tar.gz!/config/app.toml`
+    Archive(ArchivePath),
 }
 
 impl ClioPathEnum {
-    fn new(path: &OsStr, io: Option<InOut>) -> Result<Self> {
+    fn new(
+        path: &OsStr,
+        std_token: &'static str,
+        stderr_token: Option<&'static str>,
+    ) -> Result<Self> {
+        if let Some(local) = file_url::parse(path) {
+            return ClioPathEnum::new(local?.as_os_str(), std_token, stderr_token);
+        }
+
+        if let Some(data) = DataUri::parse(path) {
+            return Ok(ClioPathEnum::Data(data?));
+        }
+
+        #[cfg(unix)]
+        if let Some(fd) = FdPath::parse(path) {
+            return Ok(ClioPathEnum::Fd(fd?));
+        }
+        #[cfg(not(unix))]
+        if path.to_str().map_or(false, |path| path.starts_with("fd:")) {
+            return Err(Error::fd_unsupported_error());
+        }
+
         #[cfg(feature = "http")]
         if is_http(path) {
+            if network_disabled() {
+                return Err(Error::denied(
+                    "Urls are not allowed because CLIO_OFFLINE is set",
+                ));
+            }
             return Ok(ClioPathEnum::Http(try_to_url(path)?));
         }
 
-        if path == "-" {
-            Ok(ClioPathEnum::Std(io))
-        } else {
-            Ok(ClioPathEnum::Local(path.into()))
+        #[cfg(feature = "archive")]
+        if let Some(archive) = ArchivePath::parse(path) {
+            return Ok(ClioPathEnum::Archive(archive));
+        }
+
+        if path == std_token {
+            return Ok(ClioPathEnum::Std(StdStream::Token(std_token, None)));
         }
+        if let Some(token) = stderr_token.filter(|token| path == *token) {
+            return Ok(ClioPathEnum::Std(StdStream::Named(token, InOut::Err)));
+        }
+        #[cfg(unix)]
+        if let Some((name, stream)) = STD_NAMES.iter().find(|(name, _)| path == *name) {
+            return Ok(ClioPathEnum::Std(StdStream::Named(name, *stream)));
+        }
+        Ok(ClioPathEnum::Local(path.into()))
     }
 }
 
 impl ClioPath {
     /// Construct a new [`ClioPath`] from an string
     ///
-    /// `'-'` is treated as stdin/stdout
+    /// `'-'` is treated as stdin/stdout, on Unix `/dev/stdin`, `/dev/stdout` and `/dev/stderr`
+    /// as that stream, and a `file://` url as the local path it points to.
+    /// A `data:` url is decoded straight away, so an error is returned if it is malformed.
+    /// A local file with a name that looks like a url or descriptor, e.g. `data:x` or `fd:3`,
+    /// can be given as `./data:x` or `./fd:3`.
     pub fn new<S: AsRef<OsStr>>(path: S) -> Result<Self> {
-        Ok(ClioPath {
-            path: ClioPathEnum::new(path.as_ref(), None)?,
-            atomic: false,
-        })
+        ClioPath::with_std_token(path.as_ref(), "-", None)
+    }
+
+    /// Like [`new`](Self::new) but with `std_token` rather than `'-'` meaning stdin/stdout,
+    /// and `stderr_token` meaning stderr
+    pub(crate) fn with_std_token(
+        path: &OsStr,
+        std_token: &'static str,
+        stderr_token: Option<&'static str>,
+    ) -> Result<Self> {
+        let mut clio_path = ClioPath::from_enum(ClioPathEnum::new(path, std_token, stderr_token)?);
+        if file_url::is_file_url(path) {
+            clio_path.file_url = Some(path.to_owned());
+        }
+        Ok(clio_path)
     }
 
     /// Constructs a new [`ClioPath`] of `"-"` for stdout
     pub fn std() -> Self {
-        ClioPath {
-            path: ClioPathEnum::Std(None),
-            atomic: false,
-        }
+        ClioPath::from_enum(ClioPathEnum::Std(StdStream::Token("-", None)))
+    }
+
+    /// Constructs a new [`ClioPath`] of `"/dev/stderr"`
+    pub(crate) fn std_err() -> Self {
+        ClioPath::from_enum(ClioPathEnum::Std(StdStream::Named(
+            "/dev/stderr",
+            InOut::Err,
+        )))
     }
 
     /// Constructs a new [`ClioPath`] for a local path
     pub fn local(path: PathBuf) -> Self {
+        ClioPath::from_enum(ClioPathEnum::Local(path))
+    }
+
+    fn from_enum(path: ClioPathEnum) -> Self {
         ClioPath {
-            path: ClioPathEnum::Local(path),
+            path,
             atomic: false,
+            raw: false,
+            compression_level: None,
+            file_url: None,
+            keep_file_url: false,
+            #[cfg(feature = "http")]
+            http_config: None,
+            #[cfg(feature = "http")]
+            check_remote: false,
+            #[cfg(feature = "http")]
+            http_method: Method::Put,
+            #[cfg(feature = "http")]
+            multipart: None,
         }
     }
 
+    /// The config for HTTP requests, either the one set for this argument or the global one
+    #[cfg(feature = "http")]
+    pub(crate) fn http_config(&self) -> Arc<HttpConfig> {
+        self.http_config
+            .clone()
+            .unwrap_or_else(HttpConfig::global_arc)
+    }
+
+    /// Sets which way the std token is used, streams given by name keep the stream they named
     pub(crate) fn with_direction(self, direction: InOut) -> Self {
         ClioPath {
             path: match self.path {
-                ClioPathEnum::Std(_) => ClioPathEnum::Std(Some(direction)),
+                ClioPathEnum::Std(StdStream::Token(token, _)) => {
+                    ClioPathEnum::Std(StdStream::Token(token, Some(direction)))
+                }
                 x => x,
             },
-            atomic: self.atomic,
+            ..self
+        }
+    }
+
+    /// Like [`with_direction`](Self::with_direction) but fails if this names a std stream that
+    /// can not be used that way, e.g. reading from `/dev/stderr`
+    pub(crate) fn for_direction(self, direction: InOut) -> Result<Self> {
+        let path = self.with_direction(direction);
+        match (&path.path, direction) {
+            (ClioPathEnum::Std(std), InOut::In) if std.stream(direction) != InOut::In => {
+                Err(Error::std_direction_error())
+            }
+            (ClioPathEnum::Std(std), InOut::Out | InOut::Err)
+                if std.stream(direction) == InOut::In =>
+            {
+                Err(Error::std_direction_error())
+            }
+            _ => Ok(path),
+        }
+    }
+
+    /// The std stream this is, or `None` if it is not one, with the std token being `default`
+    /// if it is not known which way it is used
+    pub(crate) fn std_stream(&self, default: InOut) -> Option<InOut> {
+        match self.path {
+            ClioPathEnum::Std(std) => Some(std.stream(default)),
+            _ => None,
         }
     }
 
@@ -138,7 +324,18 @@ impl ClioPath {
     {
         match &mut self.path {
             ClioPathEnum::Std(_) => O::default(),
-            ClioPathEnum::Local(path) => update(path),
+            ClioPathEnum::Local(path) => {
+                let before = path.clone();
+                let r = update(path);
+                if *path != before {
+                    // the url no longer matches the path
+                    self.file_url = None;
+                }
+                r
+            }
+            ClioPathEnum::Data(_) => O::default(),
+            #[cfg(unix)]
+            ClioPathEnum::Fd(_) => O::default(),
             #[cfg(feature = "http")]
             ClioPathEnum::Http(url) => {
                 let mut path = Path::new(url.path()).to_owned();
@@ -146,6 +343,12 @@ impl ClioPath {
                 url.set_path(&path.to_string_lossy());
                 r
             }
+            #[cfg(feature = "archive")]
+            ClioPathEnum::Archive(archive) => {
+                let r = update(&mut archive.member);
+                archive.update_joined();
+                r
+            }
         }
     }
 
@@ -295,29 +498,58 @@ impl ClioPath {
         new
     }
 
-    /// Returns true if this path is stdin/stout i.e. it was created with `-`
+    /// Returns true if this path is stdin/stdout/stderr i.e. it was created with `-`
+    /// or a name like `/dev/stderr`
     pub fn is_std(&self) -> bool {
         matches!(self.path, ClioPathEnum::Std(_))
     }
 
-    /// Returns true if this [`is_std`](Self::is_std) and it would connect to a tty
+    /// Returns true if this [`is_std`](Self::is_std) and it would connect to a tty,
+    /// or it is a file descriptor connected to a tty
     pub fn is_tty(&self) -> bool {
         match self.path {
-            ClioPathEnum::Std(Some(InOut::In)) => std::io::stdin().is_terminal(),
-            ClioPathEnum::Std(Some(InOut::Out)) => std::io::stdout().is_terminal(),
-            ClioPathEnum::Std(None) => {
+            ClioPathEnum::Std(StdStream::Token(_, None)) => {
                 std::io::stdin().is_terminal() || std::io::stdout().is_terminal()
             }
+            ClioPathEnum::Std(std) => std.stream(InOut::Out).is_terminal(),
+            #[cfg(unix)]
+            ClioPathEnum::Fd(ref fd) => fd.is_tty(),
             _ => false,
         }
     }
 
+    /// Details of a `data:` url such as its media type, or `None` if this path is not one
+    ///
+    /// ```
+    /// use clio::ClioPath;
+    ///
+    /// let path = ClioPath::new("data:text/csv;base64,YSxiCjEsMgo=")?;
+    /// assert_eq!(path.data_metadata().unwrap().media_type(), "text/csv");
+    /// assert!(ClioPath::new("data.csv")?.data_metadata().is_none());
+    /// # Ok::<(), clio::Error>(())
+    /// ```
+    pub fn data_metadata(&self) -> Option<&DataMetadata> {
+        match &self.path {
+            ClioPathEnum::Data(data) => Some(data.metadata()),
+            _ => None,
+        }
+    }
+
     /// Returns true if this path is on the local file system,
-    /// as opposed to point to stdin/stout or a URL
+    /// as opposed to point to stdin/stout, a URL or a member of an archive
     pub fn is_local(&self) -> bool {
         matches!(self.path, ClioPathEnum::Local(_))
     }
 
+    /// Returns true if opening this path would contact another machine, i.e. it is a URL
+    pub fn is_remote(&self) -> bool {
+        match self.path {
+            #[cfg(feature = "http")]
+            ClioPathEnum::Http(_) => true,
+            _ => false,
+        }
+    }
+
     pub(crate) fn is_fifo(&self) -> bool {
         match &self.path {
             ClioPathEnum::Local(path) => {
@@ -328,8 +560,13 @@ impl ClioPath {
                 }
             }
             ClioPathEnum::Std(_) => true,
+            ClioPathEnum::Data(_) => false,
+            #[cfg(unix)]
+            ClioPathEnum::Fd(fd) => fd.metadata().map_or(false, |meta| is_fifo(&meta)),
             #[cfg(feature = "http")]
             ClioPathEnum::Http(_) => false,
+            #[cfg(feature = "archive")]
+            ClioPathEnum::Archive(_) => false,
         }
     }
 
@@ -354,6 +591,10 @@ impl ClioPath {
 
     /// If this is a folder returns all the files that match the filter found by looking recursively
     /// Otherwise returns just this path
+    ///
+    /// With the `s3` feature an `s3://bucket/prefix` url returns every object whose key starts
+    /// with `prefix`, and urls with the `webdav` option of their `HttpConfig` are listed
+    /// with `PROPFIND`.
     /// ```no_run
     /// use clio::has_extension;
     /// use clio::ClioPath;
@@ -368,6 +609,24 @@ impl ClioPath {
     where
         P: FnMut(&ClioPath) -> bool,
     {
+        #[cfg(feature = "http")]
+        if let ClioPathEnum::Http(url) = &self.path {
+            let config = self.http_config();
+            let urls = match url.scheme() {
+                #[cfg(feature = "s3")]
+                "s3" => crate::http::s3_list(url, &config)?,
+                _ if config.webdav => crate::http::webdav_list(url, &config)?,
+                _ => return Ok(vec![self]),
+            };
+            return Ok(urls
+                .into_iter()
+                .map(|url| ClioPath {
+                    path: ClioPathEnum::Http(url),
+                    ..self.clone()
+                })
+                .filter(|path| predicate(path))
+                .collect());
+        }
         if self.is_local() {
             let mut result = vec![];
             for entry in WalkDir::new(self.path()).follow_links(true) {
@@ -408,7 +667,11 @@ impl ClioPath {
     /// A path represented by this [`ClioPath`]
     /// If it is `-` and it is no known if it is in or out then the path will be `-`
     /// If it is `-` and it is known to be in/out then it will be the pseudo device  e.g `/dev/stdin`
+    /// If it names a std stream e.g. `/dev/stderr` it will be the pseudo device of that stream
     /// If it is a url it will be the path part of the url
+    /// If it is a member of an archive it will be the path of the member inside the archive
+    /// If it is a `data:` url it will be empty
+    /// If it is a file descriptor it will be `/dev/fd/N`
     /// ```
     /// use clio::{ClioPath, OutputPath};
     /// use std::path::Path;
@@ -430,12 +693,20 @@ impl ClioPath {
     /// ```
     pub fn path(&self) -> &Path {
         match &self.path {
-            ClioPathEnum::Std(None) => Path::new("-"),
-            ClioPathEnum::Std(Some(InOut::In)) => Path::new("/dev/stdin"),
-            ClioPathEnum::Std(Some(InOut::Out)) => Path::new("/dev/stdout"),
+            ClioPathEnum::Std(StdStream::Token(token, None)) => Path::new(token),
+            ClioPathEnum::Std(std) => match std.stream(InOut::Out) {
+                InOut::In => Path::new("/dev/stdin"),
+                InOut::Out => Path::new("/dev/stdout"),
+                InOut::Err => Path::new("/dev/stderr"),
+            },
             ClioPathEnum::Local(path) => path.as_path(),
+            ClioPathEnum::Data(_) => Path::new(""),
+            #[cfg(unix)]
+            ClioPathEnum::Fd(fd) => fd.path(),
             #[cfg(feature = "http")]
             ClioPathEnum::Http(url) => Path::new(url.path()),
+            #[cfg(feature = "archive")]
+            ClioPathEnum::Archive(archive) => archive.member.as_path(),
         }
     }
 
@@ -470,33 +741,81 @@ impl ClioPath {
     /// # Ok::<(), clio::Error>(())
     /// ```
     pub fn to_string_lossy(&self) -> Cow<'_, str> {
+        if let Some(url) = self.kept_file_url() {
+            return url.to_string_lossy();
+        }
         match &self.path {
-            ClioPathEnum::Std(_) => Cow::Borrowed("-"),
+            ClioPathEnum::Std(std) => Cow::Borrowed(std.as_str()),
             ClioPathEnum::Local(path) => path.to_string_lossy(),
+            ClioPathEnum::Data(data) => data.as_os_str().to_string_lossy(),
+            #[cfg(unix)]
+            ClioPathEnum::Fd(fd) => fd.as_os_str().to_string_lossy(),
             #[cfg(feature = "http")]
             ClioPathEnum::Http(url) => Cow::Borrowed(url.as_str()),
+            #[cfg(feature = "archive")]
+            ClioPathEnum::Archive(archive) => archive.as_os_str().to_string_lossy(),
         }
     }
 
     /// The original string represented by this [`ClioPath`]
     pub fn as_os_str(&self) -> &OsStr {
+        if let Some(url) = self.kept_file_url() {
+            return url;
+        }
         match &self.path {
-            ClioPathEnum::Std(_) => OsStr::new("-"),
+            ClioPathEnum::Std(std) => OsStr::new(std.as_str()),
             ClioPathEnum::Local(path) => path.as_os_str(),
+            ClioPathEnum::Data(data) => data.as_os_str(),
+            #[cfg(unix)]
+            ClioPathEnum::Fd(fd) => fd.as_os_str(),
             #[cfg(feature = "http")]
             ClioPathEnum::Http(url) => OsStr::new(url.as_str()),
+            #[cfg(feature = "archive")]
+            ClioPathEnum::Archive(archive) => archive.as_os_str(),
         }
     }
 
     /// Consumes the [`ClioPath`], yielding its internal OsString storage.
     pub fn to_os_string(self) -> OsString {
+        if let Some(url) = self.kept_file_url() {
+            return url.to_owned();
+        }
         match self.path {
-            ClioPathEnum::Std(_) => OsStr::new("-").to_os_string(),
+            ClioPathEnum::Std(std) => OsStr::new(std.as_str()).to_os_string(),
             ClioPathEnum::Local(path) => path.into_os_string(),
+            ClioPathEnum::Data(data) => data.as_os_str().to_os_string(),
+            #[cfg(unix)]
+            ClioPathEnum::Fd(fd) => fd.as_os_str().to_os_string(),
             #[cfg(feature = "http")]
             ClioPathEnum::Http(url) => OsStr::new(url.as_str()).to_os_string(),
+            #[cfg(feature = "archive")]
+            ClioPathEnum::Archive(archive) => archive.as_os_str().to_os_string(),
         }
     }
+
+    /// Make [`to_string_lossy`](ClioPath::to_string_lossy), [`as_os_str`](ClioPath::as_os_str)
+    /// and [`to_os_string`](ClioPath::to_os_string) return the `file://` url this path was
+    /// parsed from, rather than the local path it points to
+    ///
+    /// The url is forgotten once the path is changed, e.g. by [`set_extension`](ClioPath::set_extension).
+    ///
+    /// ```
+    /// use clio::ClioPath;
+    ///
+    /// let mut path = ClioPath::new("file:///tmp/my%20file.txt")?;
+    /// # #[cfg(unix)]
+    /// assert_eq!(path.to_string_lossy(), "/tmp/my file.txt");
+    /// path.set_keep_file_url(true);
+    /// assert_eq!(path.to_string_lossy(), "file:///tmp/my%20file.txt");
+    /// # Ok::<(), clio::Error>(())
+    /// ```
+    pub fn set_keep_file_url(&mut self, keep: bool) {
+        self.keep_file_url = keep;
+    }
+
+    fn kept_file_url(&self) -> Option<&OsStr> {
+        self.file_url.as_deref().filter(|_| self.keep_file_url)
+    }
 }
 
 impl Deref for ClioPath {